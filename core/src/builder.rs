@@ -0,0 +1,290 @@
+use crate::BindingOptions;
+use crate::Chord;
+use crate::Diagnostic;
+use crate::HotkeyBinding;
+use crate::Shell;
+use crate::ShellInit;
+use crate::Whkdrc;
+use std::time::Duration;
+
+/// Builds a [`Whkdrc`] programmatically, checking it with [`Whkdrc::validate`] in the same way as
+/// a parsed configuration
+///
+/// Keys are given as chords in the same format as whkdrc, e.g. `"alt + shift + h"`.
+#[derive(Debug, Clone)]
+pub struct WhkdrcBuilder {
+    whkdrc: Whkdrc,
+}
+
+impl WhkdrcBuilder {
+    #[must_use]
+    pub const fn new(shell: Shell) -> Self {
+        Self {
+            whkdrc: Whkdrc {
+                shell,
+                shell_args: vec![],
+                shell_init: None,
+                echo: None,
+                app_bindings: vec![],
+                bindings: vec![],
+                pause_binding: None,
+                pause_hook: None,
+                exit_hook: None,
+                timeout: None,
+                on_error: None,
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn shell(mut self, shell: Shell) -> Self {
+        self.whkdrc.shell = shell;
+        self
+    }
+
+    #[must_use]
+    pub fn shell_args(mut self, args: &[&str]) -> Self {
+        self.whkdrc.shell_args = args.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    /// Writes `commands` to every session of the shell when it starts, instead of the default
+    /// commands if `replace` is set
+    #[must_use]
+    pub fn shell_init(mut self, commands: &[&str], replace: bool) -> Self {
+        self.whkdrc.shell_init = Some(ShellInit {
+            commands: commands.iter().map(ToString::to_string).collect(),
+            replace,
+        });
+        self
+    }
+
+    #[must_use]
+    pub const fn echo(mut self, echo: bool) -> Self {
+        self.whkdrc.echo = Some(echo);
+        self
+    }
+
+    #[must_use]
+    pub fn pause_binding(mut self, keys: &str) -> Self {
+        self.whkdrc.pause_binding = Some(split_keys(keys));
+        self
+    }
+
+    #[must_use]
+    pub fn pause_hook(mut self, command: &str) -> Self {
+        self.whkdrc.pause_hook = Some(command.to_string());
+        self
+    }
+
+    #[must_use]
+    pub fn exit_hook(mut self, command: &str) -> Self {
+        self.whkdrc.exit_hook = Some(command.to_string());
+        self
+    }
+
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.whkdrc.timeout = Some(timeout);
+        self
+    }
+
+    #[must_use]
+    pub fn on_error(mut self, command: &str) -> Self {
+        self.whkdrc.on_error = Some(command.to_string());
+        self
+    }
+
+    /// Binds `keys` to `command` for every application
+    #[must_use]
+    pub fn bind(mut self, keys: &str, command: &str) -> Self {
+        self.whkdrc.bindings.push(HotkeyBinding {
+            keys: split_keys(keys),
+            command: command.to_string(),
+            process_name: None,
+            options: BindingOptions::default(),
+        });
+        self
+    }
+
+    /// Adds an entry for `process_name` to the app block for `keys`, creating the app block if
+    /// needed. `process_name` can be `Default`, and `command` can be `Ignore`.
+    #[must_use]
+    pub fn bind_app(mut self, keys: &str, process_name: &str, command: &str) -> Self {
+        let keys = split_keys(keys);
+        let binding = HotkeyBinding {
+            keys: keys.clone(),
+            command: command.to_string(),
+            process_name: Some(process_name.to_string()),
+            options: BindingOptions::default(),
+        };
+
+        let chord = Chord::new(&keys);
+        match self
+            .whkdrc
+            .app_bindings
+            .iter_mut()
+            .find(|(block, _)| Chord::new(block) == chord)
+        {
+            Some((_, bindings)) => bindings.push(binding),
+            None => self.whkdrc.app_bindings.push((keys, vec![binding])),
+        }
+
+        self
+    }
+
+    /// Returns the configuration, or every diagnostic if any of them is an error
+    pub fn build(self) -> Result<Whkdrc, Vec<Diagnostic>> {
+        let diagnostics = self.whkdrc.validate();
+        if diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(diagnostics);
+        }
+
+        Ok(self.whkdrc)
+    }
+}
+
+impl Whkdrc {
+    #[must_use]
+    pub const fn builder(shell: Shell) -> WhkdrcBuilder {
+        WhkdrcBuilder::new(shell)
+    }
+}
+
+fn split_keys(keys: &str) -> Vec<String> {
+    keys.split('+').map(|k| k.trim().to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let whkdrc = Whkdrc::builder(Shell::Pwsh)
+            .pause_binding("alt + shift + p")
+            .bind("alt + h", "komorebic focus left")
+            .bind_app("alt + n", "Default", "echo default")
+            .bind_app("alt + n", "Firefox", "Ignore")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            whkdrc,
+            Whkdrc {
+                shell: Shell::Pwsh,
+                shell_args: vec![],
+                shell_init: None,
+                echo: None,
+                app_bindings: vec![(
+                    vec![String::from("alt"), String::from("n")],
+                    vec![
+                        HotkeyBinding {
+                            keys: vec![String::from("alt"), String::from("n")],
+                            command: String::from("echo default"),
+                            process_name: Some(String::from("Default")),
+                            options: BindingOptions::default(),
+                        },
+                        HotkeyBinding {
+                            keys: vec![String::from("alt"), String::from("n")],
+                            command: String::from("Ignore"),
+                            process_name: Some(String::from("Firefox")),
+                            options: BindingOptions::default(),
+                        },
+                    ],
+                )],
+                bindings: vec![HotkeyBinding {
+                    keys: vec![String::from("alt"), String::from("h")],
+                    command: String::from("komorebic focus left"),
+                    process_name: None,
+                    options: BindingOptions::default(),
+                }],
+                pause_binding: Some(vec![
+                    String::from("alt"),
+                    String::from("shift"),
+                    String::from("p"),
+                ]),
+                pause_hook: None,
+                exit_hook: None,
+                timeout: None,
+                on_error: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_build_rejects_errors() {
+        let diagnostics = Whkdrc::builder(Shell::Cmd)
+            .bind("alt + ", "echo oops")
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic::EmptyKey {
+                keys: vec![String::from("alt"), String::new()]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_build_allows_warnings() {
+        let whkdrc = Whkdrc::builder(Shell::Cmd)
+            .bind("alt + h", "echo one")
+            .bind("alt + h", "echo two")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            whkdrc.validate(),
+            vec![Diagnostic::DuplicateBinding {
+                chord: Chord::new(&[String::from("alt"), String::from("h")])
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_app_block_key_mismatch() {
+        let mut whkdrc = Whkdrc::builder(Shell::Cmd)
+            .bind_app("alt + n", "Firefox", "echo firefox")
+            .build()
+            .unwrap();
+
+        whkdrc.app_bindings[0].1[0].keys = vec![String::from("alt"), String::from("m")];
+
+        assert_eq!(
+            whkdrc.validate(),
+            vec![Diagnostic::AppBlockKeyMismatch {
+                block: vec![String::from("alt"), String::from("n")],
+                binding: vec![String::from("alt"), String::from("m")],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_pause_binding_conflict() {
+        let whkdrc = Whkdrc::builder(Shell::Cmd)
+            .pause_binding("alt + p")
+            .bind("alt + p", "echo never")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            whkdrc.validate(),
+            vec![Diagnostic::PauseBindingConflict {
+                chord: Chord::new(&[String::from("alt"), String::from("p")])
+            }]
+        );
+    }
+
+    #[test]
+    fn test_validate_shell_init_without_shell() {
+        let whkdrc = Whkdrc::builder(Shell::None)
+            .shell_init(&["Import-Module komorebi"], false)
+            .bind("alt + h", "komorebic focus left")
+            .build()
+            .unwrap();
+
+        assert_eq!(whkdrc.validate(), vec![Diagnostic::ShellInitWithoutShell]);
+    }
+}
@@ -0,0 +1,432 @@
+use crate::expand;
+use crate::Chord;
+use crate::Resolution;
+use crate::Shell;
+use crate::Whkdrc;
+use crate::WindowContext;
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub type ExecuteError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A command to run, along with how it should be run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub command: String,
+    /// The chord of the binding the command belongs to, or `None` for hooks and commands sent
+    /// with `whkdc`
+    pub chord: Option<Chord>,
+    /// How long the command may run before it is killed, for executors which wait for commands
+    pub timeout: Option<Duration>,
+    /// Variables which are expanded in the command before the environment of whkd, and set in the
+    /// environment of processes started for the command
+    pub env: Vec<(String, String)>,
+    /// Whether the command has to run in order with the other serial jobs
+    pub serial: bool,
+    /// The shell to run the command with, or `None` for the shell set with `.shell`
+    pub shell: Option<Shell>,
+}
+
+impl Job {
+    #[must_use]
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.to_string(),
+            chord: None,
+            timeout: None,
+            env: vec![],
+            serial: false,
+            shell: None,
+        }
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        self.env
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .or_else(|| std::env::var(name).ok())
+    }
+}
+
+/// Runs the commands of bindings and hooks
+pub trait Executor: Send + Sync {
+    /// Runs `job`, whose environment variables have already been expanded
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError>;
+
+    /// Releases anything held by the executor, such as a shell session. Commands which are
+    /// executed afterwards may fail.
+    fn shutdown(&self) {}
+
+    /// Describes the state of anything the executor keeps running, such as a shell session
+    fn state(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Expands the variables of `job` and the environment in its command and runs it with `executor`
+pub fn execute(executor: &dyn Executor, job: &Job) -> Result<(), ExecuteError> {
+    executor.execute(&Job {
+        command: expand(&job.command, |name| job.var(name)),
+        ..job.clone()
+    })
+}
+
+impl Whkdrc {
+    /// Resolves `chord` in `context` along with the job which runs the resulting command, if any
+    #[must_use]
+    pub fn resolve_job(&self, chord: &Chord, context: &WindowContext) -> (Resolution, Option<Job>) {
+        let binding = self.resolve_binding(chord, context);
+        let resolution = Resolution::of(binding);
+
+        let job = match (&resolution, binding) {
+            (Resolution::Run(command), Some(binding)) => Some(Job {
+                command: command.clone(),
+                chord: Some(chord.clone()),
+                timeout: binding.options.timeout.or(self.timeout),
+                env: vec![],
+                serial: binding.options.serial,
+                shell: binding.options.shell.clone(),
+            }),
+            _ => None,
+        };
+
+        (resolution, job)
+    }
+
+    /// Resolves `chord` in `context` and runs the resulting command, if any, with `executor`
+    pub fn run(
+        &self,
+        executor: &dyn Executor,
+        chord: &Chord,
+        context: &WindowContext,
+    ) -> (Resolution, Result<(), ExecuteError>) {
+        let (resolution, job) = self.resolve_job(chord, context);
+        let result = job.map_or(Ok(()), |job| execute(executor, &job));
+
+        (resolution, result)
+    }
+
+    /// A job for a hook or another command which does not belong to a binding, using `.timeout`
+    #[must_use]
+    pub fn job(&self, command: &str) -> Job {
+        Job {
+            timeout: self.timeout,
+            ..Job::new(command)
+        }
+    }
+
+    /// The job for the `.on_error` hook when `command`, bound to `chord`, has failed. The chord,
+    /// command and exit code are given to the hook as `WHKD_CHORD`, `WHKD_COMMAND` and
+    /// `WHKD_EXIT_CODE`, which is empty if the command did not exit by itself.
+    ///
+    /// They are set in the environment of processes started for the hook, and replaced in its
+    /// command quoted for `.shell`, so that none of the failed command is run again by the hook.
+    /// For a custom shell, whose quoting is not known, they are left for the shell to expand.
+    #[must_use]
+    pub fn on_error_job(&self, chord: &str, command: &str, exit_code: Option<i32>) -> Option<Job> {
+        let hook = self.on_error.as_deref()?;
+        let env = vec![
+            (String::from("WHKD_CHORD"), chord.to_string()),
+            (String::from("WHKD_COMMAND"), command.to_string()),
+            (
+                String::from("WHKD_EXIT_CODE"),
+                exit_code.map(|code| code.to_string()).unwrap_or_default(),
+            ),
+        ];
+
+        let hook = expand(hook, |name| match env.iter().find(|(key, _)| key == name) {
+            // left empty so that a default is used instead
+            Some((_, value)) if value.is_empty() => Some(String::new()),
+            Some((_, value)) => self.shell.quote(value),
+            None => std::env::var(name).ok(),
+        });
+
+        Some(Job {
+            env,
+            // escaped so that nothing in the values is expanded again when the job is executed
+            ..self.job(&hook.replace("${", "$${"))
+        })
+    }
+}
+
+/// Records jobs instead of running them
+#[derive(Debug, Default)]
+pub struct Recorder {
+    jobs: Mutex<Vec<Job>>,
+}
+
+impl Recorder {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every job executed so far, in order
+    #[must_use]
+    pub fn jobs(&self) -> Vec<Job> {
+        self.jobs
+            .lock()
+            .map(|jobs| jobs.clone())
+            .unwrap_or_default()
+    }
+
+    /// The command of every job executed so far, in order
+    #[must_use]
+    pub fn commands(&self) -> Vec<String> {
+        self.jobs().into_iter().map(|job| job.command).collect()
+    }
+}
+
+impl Executor for Recorder {
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError> {
+        self.jobs
+            .lock()
+            .map_err(|error| error.to_string())?
+            .push(job.clone());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LineTerminator;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_run() {
+        let whkdrc = Whkdrc::builder(Shell::Pwsh)
+            .bind("alt + h", "komorebic focus left")
+            .bind_app("alt + n", "Firefox", "echo firefox")
+            .bind_app("alt + n", "Zen Browser", "Ignore")
+            .build()
+            .unwrap();
+
+        let recorder = Recorder::new();
+        let alt_n = Chord::from_str("alt + n").unwrap();
+
+        let (resolution, result) = whkdrc.run(
+            &recorder,
+            &Chord::from_str("alt + h").unwrap(),
+            &WindowContext::default(),
+        );
+        assert_eq!(
+            resolution,
+            Resolution::Run(String::from("komorebic focus left"))
+        );
+        assert!(result.is_ok());
+
+        whkdrc
+            .run(&recorder, &alt_n, &WindowContext::for_app("Firefox"))
+            .1
+            .unwrap();
+        whkdrc
+            .run(&recorder, &alt_n, &WindowContext::for_app("Zen Browser"))
+            .1
+            .unwrap();
+
+        assert_eq!(
+            recorder.commands(),
+            vec!["komorebic focus left", "echo firefox"]
+        );
+    }
+
+    #[test]
+    fn test_resolve_job() {
+        let whkdrc = Whkdrc::builder(Shell::Pwsh)
+            .bind_app("alt + n", "Firefox", "echo firefox")
+            .bind_app("alt + n", "Zen Browser", "Ignore")
+            .build()
+            .unwrap();
+        let alt_n = Chord::from_str("alt + n").unwrap();
+
+        let (resolution, job) = whkdrc.resolve_job(&alt_n, &WindowContext::for_app("Firefox"));
+        assert_eq!(resolution, Resolution::Run(String::from("echo firefox")));
+        assert_eq!(
+            job,
+            Some(Job {
+                chord: Some(alt_n.clone()),
+                ..Job::new("echo firefox")
+            })
+        );
+
+        assert_eq!(
+            whkdrc.resolve_job(&alt_n, &WindowContext::for_app("Zen Browser")),
+            (Resolution::Ignored, None)
+        );
+        assert_eq!(
+            whkdrc.resolve_job(&alt_n, &WindowContext::default()),
+            (Resolution::Unbound, None)
+        );
+    }
+
+    #[test]
+    fn test_run_unset_variable() {
+        let whkdrc = Whkdrc::builder(Shell::Bash)
+            .bind(
+                "alt + e",
+                "for f in *; do echo ${WHKD_TEST_UNSET_VARIABLE}; done",
+            )
+            .build()
+            .unwrap();
+
+        let recorder = Recorder::new();
+        let (_, result) = whkdrc.run(
+            &recorder,
+            &Chord::from_str("alt + e").unwrap(),
+            &WindowContext::default(),
+        );
+
+        assert!(result.is_ok());
+        assert_eq!(
+            recorder.commands(),
+            vec!["for f in *; do echo ${WHKD_TEST_UNSET_VARIABLE}; done"]
+        );
+    }
+
+    #[test]
+    fn test_run_options() {
+        let mut whkdrc = Whkdrc::builder(Shell::Cmd)
+            .timeout(Duration::from_secs(30))
+            .bind("alt + h", "echo global")
+            .bind("alt + l", "echo binding")
+            .build()
+            .unwrap();
+        whkdrc.bindings[1].options.timeout = Some(Duration::from_secs(5));
+        whkdrc.bindings[1].options.serial = true;
+        whkdrc.bindings[1].options.shell = Some(Shell::Pwsh);
+
+        let recorder = Recorder::new();
+        for chord in ["alt + h", "alt + l"] {
+            whkdrc
+                .run(
+                    &recorder,
+                    &Chord::from_str(chord).unwrap(),
+                    &WindowContext::default(),
+                )
+                .1
+                .unwrap();
+        }
+
+        let jobs = recorder.jobs();
+        assert_eq!(jobs[0].timeout, Some(Duration::from_secs(30)));
+        assert_eq!(jobs[1].timeout, Some(Duration::from_secs(5)));
+        assert!(!jobs[0].serial && jobs[1].serial);
+        assert_eq!(jobs[0].shell, None);
+        assert_eq!(jobs[1].shell, Some(Shell::Pwsh));
+        assert_eq!(jobs[1].chord, Some(Chord::from_str("alt + l").unwrap()));
+        assert_eq!(
+            whkdrc.job("echo hook").timeout,
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_on_error_job() {
+        let whkdrc = Whkdrc::builder(Shell::Cmd)
+            .on_error("notify ${WHKD_CHORD} ${WHKD_COMMAND} ${WHKD_EXIT_CODE:-none}")
+            .bind("alt + h", "echo left")
+            .build()
+            .unwrap();
+
+        let recorder = Recorder::new();
+        for exit_code in [Some(2), None] {
+            let job = whkdrc
+                .on_error_job("alt + h", "echo left", exit_code)
+                .unwrap();
+            execute(&recorder, &job).unwrap();
+        }
+
+        assert_eq!(
+            recorder.commands(),
+            vec![
+                r#"notify "alt + h" "echo left" "2""#,
+                r#"notify "alt + h" "echo left" none"#
+            ]
+        );
+        assert_eq!(
+            Whkdrc::builder(Shell::Cmd).build().unwrap().on_error_job(
+                "alt + h",
+                "echo left",
+                Some(1)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_on_error_job_quoting() {
+        let command = r#"it's "done" & del * ; $(rm -rf ~) ${HOME} '#"#;
+        let quoted = [
+            (
+                Shell::Cmd,
+                r#"notify "it's ""done"" & del * ; $(rm -rf ~) ${HOME} '#""#,
+            ),
+            (
+                Shell::Pwsh,
+                r#"notify 'it''s "done" & del * ; $(rm -rf ~) ${HOME} ''#'"#,
+            ),
+            (
+                Shell::Bash,
+                r#"notify 'it'\''s "done" & del * ; $(rm -rf ~) ${HOME} '\''#'"#,
+            ),
+            (
+                Shell::Nu,
+                r##"notify r##'it's "done" & del * ; $(rm -rf ~) ${HOME} '#'##"##,
+            ),
+            (
+                Shell::None,
+                r#"notify "it's \"done\" & del * ; $(rm -rf ~) ${HOME} '#""#,
+            ),
+        ];
+
+        for (shell, expected) in quoted {
+            let whkdrc = Whkdrc::builder(shell)
+                .on_error("notify ${WHKD_COMMAND}")
+                .build()
+                .unwrap();
+
+            let recorder = Recorder::new();
+            let job = whkdrc.on_error_job("alt + h", command, Some(1)).unwrap();
+            execute(&recorder, &job).unwrap();
+
+            assert_eq!(recorder.commands(), vec![expected]);
+            assert!(job
+                .env
+                .contains(&(String::from("WHKD_COMMAND"), command.to_string())));
+        }
+
+        let custom = Whkdrc::builder(Shell::Custom {
+            program: String::from("sh"),
+            terminator: LineTerminator::Lf,
+        })
+        .on_error("notify ${WHKD_COMMAND} ${WHKD_EXIT_CODE:-none}")
+        .build()
+        .unwrap();
+
+        let recorder = Recorder::new();
+        let job = custom.on_error_job("alt + h", command, None).unwrap();
+        execute(&recorder, &job).unwrap();
+
+        assert_eq!(recorder.commands(), vec!["notify ${WHKD_COMMAND} none"]);
+    }
+
+    #[test]
+    fn test_on_error_job_without_exit_code() {
+        let whkdrc = Whkdrc::builder(Shell::Cmd)
+            .on_error("notify [${WHKD_EXIT_CODE}]")
+            .build()
+            .unwrap();
+
+        let recorder = Recorder::new();
+        let job = whkdrc.on_error_job("alt + h", "echo left", None).unwrap();
+        execute(&recorder, &job).unwrap();
+
+        assert_eq!(recorder.commands(), vec!["notify []"]);
+        assert!(job
+            .env
+            .contains(&(String::from("WHKD_EXIT_CODE"), String::new())));
+    }
+}
@@ -0,0 +1,167 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpandError {
+    /// A variable with no default is not set
+    Unset(String),
+    /// A `${` has no closing `}`
+    Unterminated(String),
+}
+
+impl Display for ExpandError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unset(name) => write!(f, "environment variable '{name}' is not set"),
+            Self::Unterminated(input) => write!(f, "unterminated ${{ in '{input}'"),
+        }
+    }
+}
+
+impl std::error::Error for ExpandError {}
+
+/// Replaces `${VAR}` with the value of `VAR` as returned by `lookup`, and `${VAR:-default}` with
+/// `default` when `VAR` is unset or empty. `$${` is left as a literal `${`, and any other `$` is
+/// left as it is. Only names made of letters, digits and underscores are expanded, so shell
+/// variables such as `$Env:USERPROFILE` and `${env:USERPROFILE}` are passed through untouched, and
+/// so are variables which are not set and have no default, as they may belong to the shell.
+#[must_use]
+pub fn expand(input: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    // nothing fails unless strict
+    substitute(input, lookup, false).unwrap_or_default()
+}
+
+/// [`expand`], failing when a variable with no default is not set or a `${` is not closed
+pub fn expand_strict(
+    input: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<String, ExpandError> {
+    substitute(input, lookup, true)
+}
+
+fn substitute(
+    input: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    strict: bool,
+) -> Result<String, ExpandError> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(index) = rest.find('$') {
+        output.push_str(&rest[..index]);
+        rest = &rest[index..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            output.push_str("${");
+            rest = escaped;
+        } else if let Some(variable) = rest.strip_prefix("${") {
+            let Some(end) = variable.find('}') else {
+                if strict {
+                    return Err(ExpandError::Unterminated(input.to_string()));
+                }
+
+                output.push_str(rest);
+                return Ok(output);
+            };
+
+            let (name, default) = match variable[..end].split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (&variable[..end], None),
+            };
+
+            // leave shell syntax such as PowerShell's ${env:USERPROFILE} alone
+            if !is_variable_name(name) {
+                output.push_str("${");
+                rest = variable;
+                continue;
+            }
+
+            match (lookup(name), default) {
+                (Some(value), Some(default)) if value.is_empty() => output.push_str(default),
+                (Some(value), _) => output.push_str(&value),
+                (None, Some(default)) => output.push_str(default),
+                (None, None) if strict => return Err(ExpandError::Unset(name.to_string())),
+                (None, None) => output.push_str(&rest[..end + 3]),
+            }
+
+            rest = &variable[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// [`expand_strict`] using the environment of the current process
+pub fn expand_env(input: &str) -> Result<String, ExpandError> {
+    expand_strict(input, |name| std::env::var(name).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "USERPROFILE" => Some(String::from(r"C:\Users\me")),
+            "EMPTY" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            expand(r"${USERPROFILE}\bin\tool.exe", lookup),
+            r"C:\Users\me\bin\tool.exe"
+        );
+        assert_eq!(
+            expand("${EDITOR:-notepad} ${EMPTY:-x}", lookup),
+            "notepad x"
+        );
+        assert_eq!(expand("[${EMPTY}]", lookup), "[]");
+        assert_eq!(
+            expand(r#"& "$Env:USERPROFILE\bin" $${USERPROFILE}"#, lookup),
+            r#"& "$Env:USERPROFILE\bin" ${USERPROFILE}"#
+        );
+        assert_eq!(expand("echo $", lookup), "echo $");
+        assert_eq!(
+            expand("echo ${env:USERPROFILE} ${USERPROFILE}", lookup),
+            r"echo ${env:USERPROFILE} C:\Users\me"
+        );
+        assert_eq!(
+            expand("for f in *; do echo ${f}; done", lookup),
+            "for f in *; do echo ${f}; done"
+        );
+        assert_eq!(
+            expand("echo ${USERPROFILE} ${USERPROFILE", lookup),
+            r"echo C:\Users\me ${USERPROFILE"
+        );
+    }
+
+    #[test]
+    fn test_expand_strict() {
+        assert_eq!(expand_strict("[${EMPTY}]", lookup), Ok(String::from("[]")));
+        assert_eq!(
+            expand_strict("${EDITOR} file", lookup),
+            Err(ExpandError::Unset(String::from("EDITOR")))
+        );
+        assert_eq!(
+            expand_strict("echo ${USERPROFILE", lookup),
+            Err(ExpandError::Unterminated(String::from(
+                "echo ${USERPROFILE"
+            )))
+        );
+    }
+}
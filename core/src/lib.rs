@@ -1,30 +1,93 @@
+mod builder;
+mod execute;
+mod expand;
+mod merge;
+mod options;
+mod program;
+mod resolve;
+mod validate;
+
+pub use builder::*;
+pub use execute::*;
+pub use expand::*;
+pub use merge::*;
+pub use options::*;
+pub use program::*;
+pub use resolve::*;
+pub use validate::*;
+
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Whkdrc {
     pub shell: Shell,
+    /// Extra arguments for the program of `shell`, such as `-NoProfile`
+    pub shell_args: Vec<String>,
+    /// Written to every session of `shell` when it starts
+    pub shell_init: Option<ShellInit>,
+    /// Whether commands are written to the output log before they run, which is only done for
+    /// PowerShell when this is not set
+    pub echo: Option<bool>,
     pub app_bindings: Vec<(Vec<String>, Vec<HotkeyBinding>)>,
     pub bindings: Vec<HotkeyBinding>,
     pub pause_binding: Option<Vec<String>>,
     pub pause_hook: Option<String>,
+    pub exit_hook: Option<String>,
+    /// How long a command may run before it is killed, unless its binding sets a timeout
+    pub timeout: Option<Duration>,
+    /// Run when the command of a binding fails
+    pub on_error: Option<String>,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Shell {
     Cmd,
     Powershell,
     Pwsh,
+    Nu,
+    Bash,
+    /// The default distribution of the Windows Subsystem for Linux
+    Wsl,
+    /// Any other program which reads commands from its standard input, one per line
+    Custom {
+        program: String,
+        terminator: LineTerminator,
+    },
+    /// Commands are run as programs with arguments, without a shell
+    None,
 }
 
-#[allow(clippy::fallible_impl_from)]
-impl From<String> for Shell {
-    fn from(value: String) -> Self {
-        match value.as_str() {
-            "pwsh" => Self::Pwsh,
-            "powershell" => Self::Powershell,
-            "cmd" => Self::Cmd,
-            _ => panic!("unsupported shell"),
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellParseError(pub String);
+
+impl Display for ShellParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unsupported shell '{}', expected one of cmd | pwsh | powershell | nu | bash | wsl | none",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ShellParseError {}
+
+impl FromStr for Shell {
+    type Err = ShellParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pwsh" => Ok(Self::Pwsh),
+            "powershell" => Ok(Self::Powershell),
+            "cmd" => Ok(Self::Cmd),
+            "nu" => Ok(Self::Nu),
+            "bash" => Ok(Self::Bash),
+            "wsl" => Ok(Self::Wsl),
+            "none" => Ok(Self::None),
+            _ => Err(ShellParseError(s.to_string())),
         }
     }
 }
@@ -35,6 +98,66 @@ impl Display for Shell {
             Self::Cmd => write!(f, "cmd"),
             Self::Powershell => write!(f, "powershell"),
             Self::Pwsh => write!(f, "pwsh"),
+            Self::Nu => write!(f, "nu"),
+            Self::Bash => write!(f, "bash"),
+            Self::Wsl => write!(f, "wsl"),
+            Self::Custom { program, .. } => write!(f, "{program}"),
+            Self::None => write!(f, "none"),
+        }
+    }
+}
+
+impl Shell {
+    /// `value` quoted so that the shell passes it on as a single argument without running any of
+    /// it, or `None` for a custom shell, whose quoting is not known
+    ///
+    /// cmd has no way of quoting `%`, so environment variables in `value` are still expanded by it.
+    #[must_use]
+    pub fn quote(&self, value: &str) -> Option<String> {
+        match self {
+            Self::Powershell | Self::Pwsh => Some(format!("'{}'", value.replace('\'', "''"))),
+            Self::Bash | Self::Wsl => Some(format!("'{}'", value.replace('\'', r"'\''"))),
+            Self::Nu => {
+                // raw strings end at a quote followed by as many hashes as they start with
+                let mut hashes = String::from("#");
+                while value.contains(&format!("'{hashes}")) {
+                    hashes.push('#');
+                }
+
+                Some(format!("r{hashes}'{value}'{hashes}"))
+            }
+            // each doubled quote ends the quoted part and starts it again straight away, so
+            // nothing in between is ever outside of quotes
+            Self::Cmd => Some(format!("\"{}\"", value.replace('"', "\"\""))),
+            Self::None => Some(program::quote_argument(value)),
+            Self::Custom { .. } => None,
+        }
+    }
+}
+
+/// Commands written to a shell session when it starts or is restarted, set with `.shell_init`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShellInit {
+    pub commands: Vec<String>,
+    /// Whether the commands are written instead of the ones whkd writes by default, rather than
+    /// after them
+    pub replace: bool,
+}
+
+/// What ends each line written to a shell session
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum LineTerminator {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineTerminator {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
         }
     }
 }
@@ -44,4 +167,20 @@ pub struct HotkeyBinding {
     pub keys: Vec<String>,
     pub command: String,
     pub process_name: Option<String>,
+    pub options: BindingOptions,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_from_str() {
+        assert_eq!(Shell::from_str("wsl"), Ok(Shell::Wsl));
+        assert_eq!(Shell::from_str("nu").unwrap().to_string(), "nu");
+        assert_eq!(
+            Shell::from_str("fish"),
+            Err(ShellParseError(String::from("fish")))
+        );
+    }
 }
@@ -0,0 +1,303 @@
+use crate::Chord;
+use crate::HotkeyBinding;
+use crate::Whkdrc;
+use std::collections::HashMap;
+
+/// The command which removes a chord, or a single app block entry, defined by an earlier layer
+pub const UNBIND: &str = "Unbind";
+
+impl Whkdrc {
+    /// Applies `layer` on top of this configuration
+    ///
+    /// The shell of `layer` and its arguments are used, and its directives replace the ones they
+    /// set. The `.shell_init` of an earlier layer is dropped when `layer` changes the shell without
+    /// setting its own. A binding outside of an app block replaces every binding for its chord,
+    /// including app blocks, and an app block entry replaces the entry for the same process name in
+    /// the app block for its chord. A binding or app block entry with the command `Unbind` removes
+    /// what it would have replaced.
+    pub fn merge(&mut self, layer: Self) {
+        if layer.shell_init.is_some() || layer.shell != self.shell {
+            self.shell_init = layer.shell_init;
+        }
+
+        self.shell = layer.shell;
+        self.shell_args = layer.shell_args;
+
+        if layer.echo.is_some() {
+            self.echo = layer.echo;
+        }
+
+        if layer.pause_binding.is_some() {
+            self.pause_binding = layer.pause_binding;
+        }
+
+        if layer.pause_hook.is_some() {
+            self.pause_hook = layer.pause_hook;
+        }
+
+        if layer.exit_hook.is_some() {
+            self.exit_hook = layer.exit_hook;
+        }
+
+        if layer.timeout.is_some() {
+            self.timeout = layer.timeout;
+        }
+
+        if layer.on_error.is_some() {
+            self.on_error = layer.on_error;
+        }
+
+        for binding in layer.bindings {
+            self.unbind(&Chord::new(&binding.keys));
+
+            if binding.command != UNBIND {
+                self.bindings.push(binding);
+            }
+        }
+
+        for (keys, entries) in layer.app_bindings {
+            let chord = Chord::new(&keys);
+
+            let index = match self
+                .app_bindings
+                .iter()
+                .position(|(block, _)| Chord::new(block) == chord)
+            {
+                Some(index) => index,
+                None => {
+                    self.app_bindings.push((keys, vec![]));
+                    self.app_bindings.len() - 1
+                }
+            };
+
+            let block = &mut self.app_bindings[index].1;
+            for entry in entries {
+                block.retain(|existing| existing.process_name != entry.process_name);
+
+                if entry.command != UNBIND {
+                    block.push(entry);
+                }
+            }
+
+            if block.is_empty() {
+                self.app_bindings.remove(index);
+            }
+        }
+    }
+}
+
+/// Records which layer each binding of a merged configuration came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Provenance {
+    sources: HashMap<(Chord, Option<String>), String>,
+}
+
+impl Provenance {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the bindings of `layer` as coming from `source`. Layers must be recorded in the
+    /// same order as they are merged.
+    pub fn record(&mut self, source: &str, layer: &Whkdrc) {
+        let entries = layer
+            .bindings
+            .iter()
+            .chain(layer.app_bindings.iter().flat_map(|(_, entries)| entries));
+
+        for binding in entries {
+            let key = (Chord::new(&binding.keys), binding.process_name.clone());
+
+            if binding.command == UNBIND {
+                self.sources.remove(&key);
+            } else {
+                self.sources.insert(key, source.to_string());
+            }
+        }
+    }
+
+    /// The layer that `binding` came from
+    #[must_use]
+    pub fn source(&self, binding: &HotkeyBinding) -> Option<&str> {
+        self.sources
+            .get(&(Chord::new(&binding.keys), binding.process_name.clone()))
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Resolution;
+    use crate::Shell;
+    use crate::WindowContext;
+    use std::str::FromStr;
+
+    fn base() -> Whkdrc {
+        Whkdrc::builder(Shell::Pwsh)
+            .pause_binding("alt + shift + p")
+            .bind("alt + h", "komorebic focus left")
+            .bind("alt + l", "komorebic focus right")
+            .bind_app("alt + n", "Default", "echo default")
+            .bind_app("alt + n", "Firefox", "echo firefox")
+            .build()
+            .unwrap()
+    }
+
+    fn resolve(whkdrc: &Whkdrc, chord: &str, app: &str) -> Resolution {
+        whkdrc.resolve(
+            &Chord::from_str(chord).unwrap(),
+            &WindowContext::for_app(app),
+        )
+    }
+
+    #[test]
+    fn test_merge_overrides_and_adds() {
+        let mut whkdrc = base();
+        whkdrc.merge(
+            Whkdrc::builder(Shell::Cmd)
+                .bind("alt + h", "echo left")
+                .bind("alt + j", "echo down")
+                .build()
+                .unwrap(),
+        );
+
+        assert_eq!(whkdrc.shell, Shell::Cmd);
+        assert_eq!(
+            whkdrc.pause_binding,
+            Some(vec![
+                String::from("alt"),
+                String::from("shift"),
+                String::from("p")
+            ])
+        );
+        assert_eq!(
+            resolve(&whkdrc, "alt + h", "Firefox"),
+            Resolution::Run(String::from("echo left"))
+        );
+        assert_eq!(
+            resolve(&whkdrc, "alt + j", "Firefox"),
+            Resolution::Run(String::from("echo down"))
+        );
+        assert_eq!(
+            resolve(&whkdrc, "alt + l", "Firefox"),
+            Resolution::Run(String::from("komorebic focus right"))
+        );
+    }
+
+    #[test]
+    fn test_merge_shell_init() {
+        let mut whkdrc = Whkdrc::builder(Shell::Pwsh)
+            .shell_init(&["Import-Module komorebi"], false)
+            .bind("alt + h", "komorebic focus left")
+            .build()
+            .unwrap();
+
+        whkdrc.merge(Whkdrc::builder(Shell::Pwsh).build().unwrap());
+        assert_eq!(
+            whkdrc.shell_init.as_ref().map(|init| init.commands.clone()),
+            Some(vec![String::from("Import-Module komorebi")])
+        );
+
+        // the commands of another shell are not written to the new one
+        whkdrc.merge(Whkdrc::builder(Shell::Cmd).build().unwrap());
+        assert_eq!(whkdrc.shell_init, None);
+
+        whkdrc.merge(
+            Whkdrc::builder(Shell::Bash)
+                .shell_init(&["alias ll='ls -l'"], false)
+                .build()
+                .unwrap(),
+        );
+        assert_eq!(
+            whkdrc.shell_init.map(|init| init.commands),
+            Some(vec![String::from("alias ll='ls -l'")])
+        );
+    }
+
+    #[test]
+    fn test_merge_unbind() {
+        let mut whkdrc = base();
+        whkdrc.merge(
+            Whkdrc::builder(Shell::Pwsh)
+                .bind("alt + l", UNBIND)
+                .bind("alt + n", UNBIND)
+                .build()
+                .unwrap(),
+        );
+
+        assert_eq!(
+            whkdrc
+                .chords()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["alt + h"]
+        );
+    }
+
+    #[test]
+    fn test_merge_app_block_entries() {
+        let mut whkdrc = base();
+        whkdrc.merge(
+            Whkdrc::builder(Shell::Pwsh)
+                .bind_app("alt + n", "Firefox", UNBIND)
+                .bind_app("alt + n", "Google Chrome", "echo chrome")
+                .build()
+                .unwrap(),
+        );
+
+        assert_eq!(
+            resolve(&whkdrc, "alt + n", "Firefox"),
+            Resolution::Run(String::from("echo default"))
+        );
+        assert_eq!(
+            resolve(&whkdrc, "alt + n", "Google Chrome"),
+            Resolution::Run(String::from("echo chrome"))
+        );
+
+        whkdrc.merge(
+            Whkdrc::builder(Shell::Pwsh)
+                .bind_app("alt + n", "Default", UNBIND)
+                .bind_app("alt + n", "Google Chrome", UNBIND)
+                .build()
+                .unwrap(),
+        );
+
+        assert!(!whkdrc.is_app_specific(&Chord::from_str("alt + n").unwrap()));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let base = base();
+        let layer = Whkdrc::builder(Shell::Pwsh)
+            .bind("alt + h", "echo left")
+            .bind_app("alt + n", "Firefox", UNBIND)
+            .build()
+            .unwrap();
+
+        let mut provenance = Provenance::new();
+        provenance.record("base", &base);
+        provenance.record("local", &layer);
+
+        let mut whkdrc = base;
+        whkdrc.merge(layer);
+
+        let sources = whkdrc
+            .bindings
+            .iter()
+            .chain(whkdrc.app_bindings.iter().flat_map(|(_, entries)| entries))
+            .map(|binding| (binding.command.as_str(), provenance.source(binding)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            sources,
+            vec![
+                ("komorebic focus right", Some("base")),
+                ("echo left", Some("local")),
+                ("echo default", Some("base")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use crate::Shell;
+use std::time::Duration;
+
+/// Settings for how the command of a single binding is run, given in whkdrc as a list in square
+/// brackets before the command, e.g. `alt + h : [cmd, serial, timeout=5s] dir`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BindingOptions {
+    /// How long the command may run before it is killed, overriding `.timeout`
+    pub timeout: Option<Duration>,
+    /// Whether the command has to run after the commands of other serial bindings have finished,
+    /// rather than in any idle shell session
+    pub serial: bool,
+    /// The shell to run the command with instead of the one set with `.shell`
+    pub shell: Option<Shell>,
+}
+
+impl BindingOptions {
+    /// Fills in the options which are not set with the ones from `defaults`, such as the options
+    /// of the app block a binding belongs to
+    #[must_use]
+    pub fn or(self, defaults: &Self) -> Self {
+        Self {
+            timeout: self.timeout.or(defaults.timeout),
+            serial: self.serial || defaults.serial,
+            shell: self.shell.or_else(|| defaults.shell.clone()),
+        }
+    }
+}
+
+/// Parses a duration made of a whole number and a unit of `ms`, `s` or `m`, e.g. `500ms`
+#[must_use]
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let input = input.trim();
+    let split = input.find(|c: char| !c.is_ascii_digit())?;
+    let value = input[..split].parse::<u64>().ok()?;
+
+    match &input[split..] {
+        "ms" => Some(Duration::from_millis(value)),
+        "s" => Some(Duration::from_secs(value)),
+        "m" => value.checked_mul(60).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5s"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("5"), None);
+        assert_eq!(parse_duration("s"), None);
+        assert_eq!(parse_duration("5h"), None);
+    }
+
+    #[test]
+    fn test_or() {
+        let block = BindingOptions {
+            timeout: Some(Duration::from_secs(5)),
+            serial: true,
+            shell: Some(Shell::Cmd),
+        };
+        let entry = BindingOptions {
+            shell: Some(Shell::Pwsh),
+            ..BindingOptions::default()
+        };
+
+        assert_eq!(
+            entry.or(&block),
+            BindingOptions {
+                timeout: Some(Duration::from_secs(5)),
+                serial: true,
+                shell: Some(Shell::Pwsh),
+            }
+        );
+    }
+}
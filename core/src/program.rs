@@ -0,0 +1,248 @@
+use std::ffi::OsStr;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitError {
+    /// The command has no program
+    Empty,
+    /// A quote has no closing quote
+    UnterminatedQuote(String),
+}
+
+impl Display for SplitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the command is empty"),
+            Self::UnterminatedQuote(command) => write!(f, "unterminated quote in '{command}'"),
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+/// Splits `command` into a program and its arguments for running without a shell
+///
+/// Arguments are separated by whitespace and can be grouped with double quotes, following the
+/// rules of `CommandLineToArgvW`: backslashes are only special before a double quote, where each
+/// pair is a single backslash and an odd one makes the quote literal, and `""` inside double
+/// quotes is a literal double quote. Single quotes are kept as they are, and so are Windows paths.
+pub fn split_command(command: &str) -> Result<Vec<String>, SplitError> {
+    let mut arguments = vec![];
+    let mut current: Option<String> = None;
+    let mut quoted = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let mut backslashes = 1;
+                while chars.next_if_eq(&'\\').is_some() {
+                    backslashes += 1;
+                }
+
+                let argument = current.get_or_insert_with(String::new);
+                if chars.peek() == Some(&'"') {
+                    argument.extend(std::iter::repeat_n('\\', backslashes / 2));
+
+                    if backslashes % 2 == 1 {
+                        argument.push('"');
+                        chars.next();
+                    }
+                } else {
+                    argument.extend(std::iter::repeat_n('\\', backslashes));
+                }
+            }
+            '"' if quoted && chars.next_if_eq(&'"').is_some() => {
+                current.get_or_insert_with(String::new).push('"');
+            }
+            '"' => {
+                current.get_or_insert_with(String::new);
+                quoted = !quoted;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some(argument) = current.take() {
+                    arguments.push(argument);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quoted {
+        return Err(SplitError::UnterminatedQuote(command.to_string()));
+    }
+
+    if let Some(argument) = current {
+        arguments.push(argument);
+    }
+
+    if arguments.is_empty() {
+        return Err(SplitError::Empty);
+    }
+
+    Ok(arguments)
+}
+
+/// `value` quoted so that [`split_command`] reads it back as a single argument
+pub(crate) fn quote_argument(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+
+    for c in value.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+
+        // backslashes are only doubled where they would otherwise escape a quote
+        let escapes = if c == '"' {
+            backslashes * 2 + 1
+        } else {
+            backslashes
+        };
+        quoted.extend(std::iter::repeat_n('\\', escapes));
+        quoted.push(c);
+        backslashes = 0;
+    }
+
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Finds `program` in the directories of `path`, trying each extension in `pathext` (e.g.
+/// `.EXE;.CMD`) when `program` has no extension. A program with a directory is only looked for
+/// in that directory.
+#[must_use]
+pub fn find_program(
+    program: &str,
+    path: Option<&OsStr>,
+    pathext: Option<&OsStr>,
+) -> Option<PathBuf> {
+    let program = Path::new(program);
+
+    let mut extensions = vec![];
+    if program.extension().is_none() {
+        if let Some(pathext) = pathext {
+            extensions.extend(
+                pathext
+                    .to_string_lossy()
+                    .split(';')
+                    .filter(|extension| !extension.is_empty())
+                    .map(String::from),
+            );
+        }
+    }
+
+    let candidates = |dir: &Path| {
+        let program = dir.join(program);
+        let mut candidates = extensions
+            .iter()
+            .map(|extension| {
+                let mut candidate = program.clone().into_os_string();
+                candidate.push(extension);
+                PathBuf::from(candidate)
+            })
+            .collect::<Vec<_>>();
+
+        candidates.push(program);
+        candidates
+    };
+
+    if program
+        .parent()
+        .is_some_and(|parent| parent != Path::new(""))
+    {
+        return candidates(Path::new("")).into_iter().find(|c| c.is_file());
+    }
+
+    path.into_iter()
+        .flat_map(std::env::split_paths)
+        .flat_map(|dir| candidates(&dir))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command("komorebic focus left").unwrap(),
+            vec!["komorebic", "focus", "left"]
+        );
+        assert_eq!(
+            split_command(r#"  "C:\Program Files\tool.exe"  --name "two words" a"b"c "#).unwrap(),
+            vec![r"C:\Program Files\tool.exe", "--name", "two words", "abc"]
+        );
+        assert_eq!(
+            split_command(r#"echo "say \"hi\"" "" "say ""bye""""#).unwrap(),
+            vec!["echo", r#"say "hi""#, "", r#"say "bye""#]
+        );
+        assert_eq!(
+            split_command(r#"robocopy "C:\dir\\" C:\backup\ \\server\share"#).unwrap(),
+            vec!["robocopy", r"C:\dir\", r"C:\backup\", r"\\server\share"]
+        );
+        assert_eq!(
+            split_command("notify it's done").unwrap(),
+            vec!["notify", "it's", "done"]
+        );
+    }
+
+    #[test]
+    fn test_quote_argument() {
+        for value in [
+            "",
+            "komorebic focus left",
+            r#"echo "hi" & del *"#,
+            r"C:\dir\",
+            r#"a\"b\\"c"#,
+        ] {
+            assert_eq!(
+                split_command(&format!("notify {}", quote_argument(value))).unwrap(),
+                vec!["notify", value]
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_command_errors() {
+        assert_eq!(split_command("   "), Err(SplitError::Empty));
+        assert_eq!(
+            split_command(r#"echo "oops"#),
+            Err(SplitError::UnterminatedQuote(String::from(r#"echo "oops"#)))
+        );
+    }
+
+    #[test]
+    fn test_find_program() {
+        let dir = std::env::temp_dir().join(format!("whkd-find-program-{}", std::process::id()));
+        let bin = dir.join("bin");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::write(bin.join("tool.cmd"), "").unwrap();
+        std::fs::write(bin.join("plain"), "").unwrap();
+
+        let path = std::env::join_paths([dir.join("missing"), bin.clone()]).unwrap();
+        let pathext = OsStr::new(".EXE;.cmd");
+
+        assert_eq!(
+            find_program("tool", Some(&path), Some(pathext)),
+            Some(bin.join("tool.cmd"))
+        );
+        assert_eq!(
+            find_program("plain", Some(&path), Some(pathext)),
+            Some(bin.join("plain"))
+        );
+        assert_eq!(find_program("missing", Some(&path), Some(pathext)), None);
+        assert_eq!(
+            find_program(&bin.join("tool").to_string_lossy(), None, Some(pathext)),
+            Some(bin.join("tool.cmd"))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,471 @@
+use crate::BindingOptions;
+use crate::HotkeyBinding;
+use crate::Whkdrc;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// A key combination, normalized so that key names are compared case-insensitively and the order
+/// of the modifiers does not matter
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub modifiers: Vec<String>,
+    pub trigger: String,
+}
+
+impl Chord {
+    #[must_use]
+    pub fn new(keys: &[String]) -> Self {
+        let mut keys = keys
+            .iter()
+            .map(|k| k.trim().to_lowercase())
+            .collect::<Vec<_>>();
+
+        let trigger = keys.pop().unwrap_or_default();
+        keys.sort();
+        keys.dedup();
+
+        Self {
+            modifiers: keys,
+            trigger,
+        }
+    }
+
+    /// The key names of the chord, with the trigger key last
+    #[must_use]
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = self.modifiers.clone();
+        keys.push(self.trigger.clone());
+        keys
+    }
+}
+
+impl From<&[String]> for Chord {
+    fn from(keys: &[String]) -> Self {
+        Self::new(keys)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChordParseError(pub String);
+
+impl Display for ChordParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}' is not a valid chord", self.0)
+    }
+}
+
+impl std::error::Error for ChordParseError {}
+
+impl FromStr for Chord {
+    type Err = ChordParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keys = s.split('+').map(str::trim).collect::<Vec<_>>();
+        if keys.iter().any(|k| k.is_empty()) {
+            return Err(ChordParseError(s.to_string()));
+        }
+
+        Ok(Self::new(
+            &keys.into_iter().map(String::from).collect::<Vec<_>>(),
+        ))
+    }
+}
+
+impl Display for Chord {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{modifier} + ")?;
+        }
+
+        write!(f, "{}", self.trigger)
+    }
+}
+
+/// The state of the desktop that app-specific bindings are resolved against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WindowContext {
+    /// The application name of the active window, if there is one
+    pub app_name: Option<String>,
+}
+
+impl WindowContext {
+    #[must_use]
+    pub fn for_app(app_name: &str) -> Self {
+        Self {
+            app_name: Some(app_name.to_string()),
+        }
+    }
+}
+
+/// The outcome of pressing a chord in a given [`WindowContext`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// The command should be run
+    Run(String),
+    /// The matching app block entry is `Ignore`
+    Ignored,
+    /// Nothing is bound to the chord for this context
+    Unbound,
+}
+
+impl Resolution {
+    /// The outcome of resolving a chord to `binding`
+    pub(crate) fn of(binding: Option<&HotkeyBinding>) -> Self {
+        match binding {
+            Some(binding) if binding.command == "Ignore" && binding.process_name.is_some() => {
+                Self::Ignored
+            }
+            Some(binding) => Self::Run(binding.command.clone()),
+            None => Self::Unbound,
+        }
+    }
+}
+
+impl Whkdrc {
+    /// Returns the binding that handles `chord` in `context`, if any.
+    ///
+    /// Precedence, from highest to lowest:
+    ///
+    /// 1. an app block entry whose process name equals the active app name
+    /// 2. an app block `Default` entry
+    /// 3. a binding outside of an app block
+    ///
+    /// When several app block entries match, the one defined last wins. When a chord is bound more
+    /// than once outside of app blocks, the binding defined first wins, as it is the one whose
+    /// hotkey is registered.
+    #[must_use]
+    pub fn resolve_binding(
+        &self,
+        chord: &Chord,
+        context: &WindowContext,
+    ) -> Option<&HotkeyBinding> {
+        let mut matched = None;
+        let mut default = None;
+
+        for (keys, app_bindings) in &self.app_bindings {
+            if Chord::new(keys) != *chord {
+                continue;
+            }
+
+            for binding in app_bindings {
+                match binding.process_name.as_deref() {
+                    Some("Default") => default = Some(binding),
+                    Some(process_name) if context.app_name.as_deref() == Some(process_name) => {
+                        matched = Some(binding);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        matched.or(default).or_else(|| {
+            self.bindings
+                .iter()
+                .find(|binding| Chord::new(&binding.keys) == *chord)
+        })
+    }
+
+    /// Resolves what should happen when `chord` is pressed in `context`
+    #[must_use]
+    pub fn resolve(&self, chord: &Chord, context: &WindowContext) -> Resolution {
+        Resolution::of(self.resolve_binding(chord, context))
+    }
+
+    /// Whether `chord` has an app block, meaning its resolution depends on the [`WindowContext`]
+    #[must_use]
+    pub fn is_app_specific(&self, chord: &Chord) -> bool {
+        self.app_bindings
+            .iter()
+            .any(|(keys, _)| Chord::new(keys) == *chord)
+    }
+
+    /// Every distinct chord in this configuration, in the order they are first defined, with app
+    /// blocks before other bindings
+    #[must_use]
+    pub fn chords(&self) -> Vec<Chord> {
+        let mut chords: Vec<Chord> = vec![];
+
+        let keys = self
+            .app_bindings
+            .iter()
+            .map(|(keys, _)| keys)
+            .chain(self.bindings.iter().map(|binding| &binding.keys));
+
+        for keys in keys {
+            let chord = Chord::new(keys);
+            if !chords.contains(&chord) {
+                chords.push(chord);
+            }
+        }
+
+        chords
+    }
+
+    /// Removes every binding for `chord`, including its app block, returning whether anything was
+    /// removed
+    pub fn unbind(&mut self, chord: &Chord) -> bool {
+        let before = self.app_bindings.len() + self.bindings.len();
+
+        self.app_bindings
+            .retain(|(keys, _)| Chord::new(keys) != *chord);
+        self.bindings
+            .retain(|binding| Chord::new(&binding.keys) != *chord);
+
+        before != self.app_bindings.len() + self.bindings.len()
+    }
+
+    /// This configuration with each chord in `overrides` bound to its command in place of its
+    /// bindings, or left unbound when the command is `None`
+    #[must_use]
+    pub fn overlay(&self, overrides: &[(Chord, Option<String>)]) -> Self {
+        let mut whkdrc = self.clone();
+        for (chord, command) in overrides {
+            whkdrc.unbind(chord);
+
+            if let Some(command) = command {
+                whkdrc.bindings.push(HotkeyBinding {
+                    keys: chord.keys(),
+                    command: command.clone(),
+                    process_name: None,
+                    options: BindingOptions::default(),
+                });
+            }
+        }
+
+        whkdrc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Diagnostic;
+    use crate::Shell;
+
+    fn binding(keys: &[&str], command: &str, process_name: Option<&str>) -> HotkeyBinding {
+        HotkeyBinding {
+            keys: keys.iter().map(ToString::to_string).collect(),
+            command: command.to_string(),
+            process_name: process_name.map(String::from),
+            options: BindingOptions::default(),
+        }
+    }
+
+    fn whkdrc() -> Whkdrc {
+        let keys = vec![String::from("alt"), String::from("n")];
+
+        Whkdrc {
+            shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
+            app_bindings: vec![(
+                keys,
+                vec![
+                    binding(&["alt", "n"], "echo default", Some("Default")),
+                    binding(&["alt", "n"], "echo firefox", Some("Firefox")),
+                    binding(&["alt", "n"], "Ignore", Some("Zen Browser")),
+                ],
+            )],
+            bindings: vec![
+                binding(&["alt", "h"], "komorebic focus left", None),
+                binding(&["alt", "shift", "h"], "komorebic move left", None),
+            ],
+            pause_binding: None,
+            pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
+        }
+    }
+
+    #[test]
+    fn test_chord_normalization() {
+        let a = Chord::from_str("Shift + ALT + h").unwrap();
+        let b = Chord::from_str("alt+shift+h").unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(a.to_string(), "alt + shift + h");
+        assert!(Chord::from_str("alt + ").is_err());
+    }
+
+    #[test]
+    fn test_exact_app_match_wins_over_default() {
+        let chord = Chord::from_str("alt + n").unwrap();
+
+        assert_eq!(
+            whkdrc().resolve(&chord, &WindowContext::for_app("Firefox")),
+            Resolution::Run(String::from("echo firefox"))
+        );
+    }
+
+    #[test]
+    fn test_default_applies_to_unmatched_apps() {
+        let chord = Chord::from_str("alt + n").unwrap();
+
+        assert_eq!(
+            whkdrc().resolve(&chord, &WindowContext::for_app("Google Chrome")),
+            Resolution::Run(String::from("echo default"))
+        );
+        assert_eq!(
+            whkdrc().resolve(&chord, &WindowContext::default()),
+            Resolution::Run(String::from("echo default"))
+        );
+    }
+
+    #[test]
+    fn test_ignore() {
+        let chord = Chord::from_str("alt + n").unwrap();
+
+        assert_eq!(
+            whkdrc().resolve(&chord, &WindowContext::for_app("Zen Browser")),
+            Resolution::Ignored
+        );
+    }
+
+    #[test]
+    fn test_app_block_without_default_falls_back_to_global_binding() {
+        let mut whkdrc = whkdrc();
+        whkdrc.app_bindings[0].1.remove(0);
+        whkdrc
+            .bindings
+            .push(binding(&["alt", "n"], "echo global", None));
+
+        let chord = Chord::from_str("alt + n").unwrap();
+
+        assert_eq!(
+            whkdrc.resolve(&chord, &WindowContext::for_app("Firefox")),
+            Resolution::Run(String::from("echo firefox"))
+        );
+        assert_eq!(
+            whkdrc.resolve(&chord, &WindowContext::for_app("Google Chrome")),
+            Resolution::Run(String::from("echo global"))
+        );
+    }
+
+    #[test]
+    fn test_app_block_without_default_is_unbound_for_other_apps() {
+        let mut whkdrc = whkdrc();
+        whkdrc.app_bindings[0].1.remove(0);
+
+        let chord = Chord::from_str("alt + n").unwrap();
+
+        assert_eq!(
+            whkdrc.resolve(&chord, &WindowContext::for_app("Google Chrome")),
+            Resolution::Unbound
+        );
+        assert!(whkdrc.is_app_specific(&chord));
+    }
+
+    #[test]
+    fn test_global_bindings() {
+        let whkdrc = whkdrc();
+
+        assert_eq!(
+            whkdrc.resolve(
+                &Chord::from_str("shift + alt + h").unwrap(),
+                &WindowContext::for_app("Firefox")
+            ),
+            Resolution::Run(String::from("komorebic move left"))
+        );
+        assert_eq!(
+            whkdrc.resolve(
+                &Chord::from_str("alt + x").unwrap(),
+                &WindowContext::default()
+            ),
+            Resolution::Unbound
+        );
+    }
+
+    #[test]
+    fn test_duplicate_definitions() {
+        let mut whkdrc = whkdrc();
+        whkdrc
+            .bindings
+            .push(binding(&["alt", "h"], "komorebic focus right", None));
+        whkdrc.app_bindings[0].1.push(binding(
+            &["alt", "n"],
+            "echo firefox again",
+            Some("Firefox"),
+        ));
+
+        // the first global binding is the one registered, so it is the one which runs
+        assert_eq!(
+            whkdrc.resolve(
+                &Chord::from_str("alt + h").unwrap(),
+                &WindowContext::default()
+            ),
+            Resolution::Run(String::from("komorebic focus left"))
+        );
+
+        // while the last app block entry for a process replaces the earlier ones
+        assert_eq!(
+            whkdrc.resolve(
+                &Chord::from_str("alt + n").unwrap(),
+                &WindowContext::for_app("Firefox")
+            ),
+            Resolution::Run(String::from("echo firefox again"))
+        );
+    }
+
+    #[test]
+    fn test_chords() {
+        assert_eq!(
+            whkdrc()
+                .chords()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec!["alt + n", "alt + h", "alt + shift + h"]
+        );
+    }
+
+    #[test]
+    fn test_unbind() {
+        let mut whkdrc = whkdrc();
+        let chord = Chord::from_str("alt + n").unwrap();
+
+        assert!(whkdrc.unbind(&chord));
+        assert!(!whkdrc.unbind(&chord));
+        assert_eq!(
+            whkdrc.resolve(&chord, &WindowContext::for_app("Firefox")),
+            Resolution::Unbound
+        );
+        assert!(!whkdrc.is_app_specific(&chord));
+        assert_eq!(whkdrc.chords().len(), 2);
+    }
+
+    #[test]
+    fn test_overlay() {
+        let mut loaded = whkdrc();
+        loaded.pause_binding = Some(vec![String::from("alt"), String::from("p")]);
+
+        let pause = Chord::from_str("alt + p").unwrap();
+        let focus = Chord::from_str("alt + h").unwrap();
+        let firefox = Chord::from_str("alt + n").unwrap();
+
+        assert_eq!(loaded.overlay(&[]), loaded);
+
+        // binding the pause chord at runtime is reported in the same way as in whkdrc
+        let whkdrc = loaded.overlay(&[(pause.clone(), Some(String::from("echo paused")))]);
+        assert!(whkdrc
+            .validate()
+            .contains(&Diagnostic::PauseBindingConflict { chord: pause }));
+
+        // while unbinding and replacing other chords leaves a valid configuration
+        let whkdrc = loaded.overlay(&[
+            (focus.clone(), None),
+            (firefox.clone(), Some(String::from("echo anywhere"))),
+        ]);
+        assert!(whkdrc.validate().iter().all(|d| !d.is_error()));
+        assert_eq!(
+            whkdrc.resolve(&focus, &WindowContext::default()),
+            Resolution::Unbound
+        );
+        assert_eq!(
+            whkdrc.resolve(&firefox, &WindowContext::for_app("Firefox")),
+            Resolution::Run(String::from("echo anywhere"))
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use crate::Chord;
+use crate::Shell;
+use crate::Whkdrc;
+use std::fmt::Display;
+use std::fmt::Formatter;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The configuration works, but probably not the way it was intended to
+    Warning,
+    /// The configuration cannot be used
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Warning => write!(f, "warning"),
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found by [`Whkdrc::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A binding has no keys
+    EmptyChord { command: String },
+    /// The pause binding has no keys
+    EmptyPauseBinding,
+    /// A binding has a key with an empty name
+    EmptyKey { keys: Vec<String> },
+    /// An app block has no entries
+    EmptyAppBlock { keys: Vec<String> },
+    /// An app block entry has different keys to the app block it belongs to
+    AppBlockKeyMismatch {
+        block: Vec<String>,
+        binding: Vec<String>,
+    },
+    /// An app block entry has no process name
+    MissingProcessName { keys: Vec<String> },
+    /// A binding outside of an app block has a process name
+    UnexpectedProcessName {
+        keys: Vec<String>,
+        process_name: String,
+    },
+    /// A binding has an empty command
+    EmptyCommand { keys: Vec<String> },
+    /// A chord is bound more than once outside of app blocks, only the first binding is used
+    DuplicateBinding { chord: Chord },
+    /// A process name appears more than once for the same chord, only the last entry is used
+    DuplicateAppEntry { chord: Chord, process_name: String },
+    /// The pause chord is also bound to a command which will never run
+    PauseBindingConflict { chord: Chord },
+    /// A pause hook is set without a pause binding, so it will never run
+    PauseHookWithoutBinding,
+    /// `.shell_init` is set with `.shell none`, so there is no session to write it to
+    ShellInitWithoutShell,
+}
+
+impl Diagnostic {
+    #[must_use]
+    pub const fn severity(&self) -> Severity {
+        match self {
+            Self::EmptyChord { .. }
+            | Self::EmptyPauseBinding
+            | Self::EmptyKey { .. }
+            | Self::EmptyAppBlock { .. }
+            | Self::AppBlockKeyMismatch { .. }
+            | Self::MissingProcessName { .. }
+            | Self::UnexpectedProcessName { .. } => Severity::Error,
+            Self::EmptyCommand { .. }
+            | Self::DuplicateBinding { .. }
+            | Self::DuplicateAppEntry { .. }
+            | Self::PauseBindingConflict { .. }
+            | Self::PauseHookWithoutBinding
+            | Self::ShellInitWithoutShell => Severity::Warning,
+        }
+    }
+
+    #[must_use]
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.severity())?;
+
+        match self {
+            Self::EmptyChord { command } => write!(f, "the binding for '{command}' has no keys"),
+            Self::EmptyPauseBinding => write!(f, "the pause binding has no keys"),
+            Self::EmptyKey { keys } => {
+                write!(f, "'{}' contains an empty key name", keys.join(" + "))
+            }
+            Self::EmptyAppBlock { keys } => {
+                write!(f, "the app block for '{}' has no entries", keys.join(" + "))
+            }
+            Self::AppBlockKeyMismatch { block, binding } => write!(
+                f,
+                "the app block for '{}' contains an entry for '{}'",
+                block.join(" + "),
+                binding.join(" + ")
+            ),
+            Self::MissingProcessName { keys } => write!(
+                f,
+                "an entry in the app block for '{}' has no process name",
+                keys.join(" + ")
+            ),
+            Self::UnexpectedProcessName { keys, process_name } => write!(
+                f,
+                "the binding for '{}' is outside of an app block but has the process name '{process_name}'",
+                keys.join(" + ")
+            ),
+            Self::EmptyCommand { keys } => {
+                write!(f, "'{}' is bound to an empty command", keys.join(" + "))
+            }
+            Self::DuplicateBinding { chord } => write!(
+                f,
+                "'{chord}' is bound more than once, only the first binding will be used"
+            ),
+            Self::DuplicateAppEntry {
+                chord,
+                process_name,
+            } => write!(
+                f,
+                "'{process_name}' appears more than once for '{chord}', only the last entry will be used"
+            ),
+            Self::PauseBindingConflict { chord } => write!(
+                f,
+                "'{chord}' is the pause binding, so the command bound to it will never run"
+            ),
+            Self::PauseHookWithoutBinding => {
+                write!(f, "a pause hook is set without a pause binding")
+            }
+            Self::ShellInitWithoutShell => {
+                write!(f, ".shell_init is set with .shell none, so it will never run")
+            }
+        }
+    }
+}
+
+impl Whkdrc {
+    /// Checks the invariants that the parser guarantees for parsed configurations, along with
+    /// likely mistakes that are reported as warnings
+    #[must_use]
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+
+        for (keys, app_bindings) in &self.app_bindings {
+            if keys.is_empty() {
+                for binding in app_bindings {
+                    diagnostics.push(Diagnostic::EmptyChord {
+                        command: binding.command.clone(),
+                    });
+                }
+            }
+
+            validate_keys(keys, &mut diagnostics);
+
+            if app_bindings.is_empty() {
+                diagnostics.push(Diagnostic::EmptyAppBlock { keys: keys.clone() });
+            }
+
+            let chord = Chord::new(keys);
+            let mut seen: Vec<&str> = vec![];
+
+            for binding in app_bindings {
+                if Chord::new(&binding.keys) != chord {
+                    diagnostics.push(Diagnostic::AppBlockKeyMismatch {
+                        block: keys.clone(),
+                        binding: binding.keys.clone(),
+                    });
+                }
+
+                if binding.command.trim().is_empty() {
+                    diagnostics.push(Diagnostic::EmptyCommand { keys: keys.clone() });
+                }
+
+                match binding.process_name.as_deref() {
+                    None => diagnostics.push(Diagnostic::MissingProcessName { keys: keys.clone() }),
+                    Some(process_name) if seen.contains(&process_name) => {
+                        diagnostics.push(Diagnostic::DuplicateAppEntry {
+                            chord: chord.clone(),
+                            process_name: process_name.to_string(),
+                        });
+                    }
+                    Some(process_name) => seen.push(process_name),
+                }
+            }
+        }
+
+        let mut seen: Vec<Chord> = vec![];
+
+        for binding in &self.bindings {
+            if binding.keys.is_empty() {
+                diagnostics.push(Diagnostic::EmptyChord {
+                    command: binding.command.clone(),
+                });
+                continue;
+            }
+
+            validate_keys(&binding.keys, &mut diagnostics);
+
+            if let Some(process_name) = &binding.process_name {
+                diagnostics.push(Diagnostic::UnexpectedProcessName {
+                    keys: binding.keys.clone(),
+                    process_name: process_name.clone(),
+                });
+            }
+
+            if binding.command.trim().is_empty() {
+                diagnostics.push(Diagnostic::EmptyCommand {
+                    keys: binding.keys.clone(),
+                });
+            }
+
+            let chord = Chord::new(&binding.keys);
+            if seen.contains(&chord) {
+                diagnostics.push(Diagnostic::DuplicateBinding {
+                    chord: chord.clone(),
+                });
+            }
+
+            seen.push(chord);
+        }
+
+        match &self.pause_binding {
+            Some(keys) if keys.is_empty() => diagnostics.push(Diagnostic::EmptyPauseBinding),
+            Some(keys) => {
+                validate_keys(keys, &mut diagnostics);
+
+                let chord = Chord::new(keys);
+                if self.chords().contains(&chord) {
+                    diagnostics.push(Diagnostic::PauseBindingConflict { chord });
+                }
+            }
+            None if self.pause_hook.is_some() => {
+                diagnostics.push(Diagnostic::PauseHookWithoutBinding);
+            }
+            None => {}
+        }
+
+        if self.shell == Shell::None && self.shell_init.is_some() {
+            diagnostics.push(Diagnostic::ShellInitWithoutShell);
+        }
+
+        diagnostics
+    }
+}
+
+fn validate_keys(keys: &[String], diagnostics: &mut Vec<Diagnostic>) {
+    if keys.iter().any(|k| k.trim().is_empty()) {
+        diagnostics.push(Diagnostic::EmptyKey {
+            keys: keys.to_vec(),
+        });
+    }
+}
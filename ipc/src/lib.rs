@@ -0,0 +1,565 @@
+use interprocess::local_socket::prelude::*;
+#[cfg(unix)]
+use interprocess::local_socket::GenericFilePath;
+#[cfg(windows)]
+use interprocess::local_socket::GenericNamespaced;
+use interprocess::local_socket::ListenerOptions;
+use interprocess::local_socket::Name;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use thiserror::Error;
+use whkd_core::ExecuteError;
+use whkd_core::Executor;
+use whkd_core::Job;
+
+pub use interprocess::local_socket::Listener;
+pub use interprocess::local_socket::RecvHalf;
+pub use interprocess::local_socket::SendHalf;
+pub use interprocess::local_socket::Stream;
+
+/// The name of the endpoint whkd listens on, a named pipe on Windows and a Unix socket elsewhere,
+/// when it runs with the whkdrc it found by itself rather than one given with `--config`
+pub const SOCKET_NAME: &str = "whkd.sock";
+
+/// Identifies the whkd running with the whkdrc at `config`, the same for every build and for every
+/// way of writing the path
+#[must_use]
+pub fn instance_key(config: &Path) -> String {
+    let config = std::fs::canonicalize(config).unwrap_or_else(|_| config.to_path_buf());
+
+    // 64-bit FNV-1a, which unlike the hashers of std is guaranteed not to change
+    let hash = config
+        .to_string_lossy()
+        .to_lowercase()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    format!("whkd-{hash:016x}")
+}
+
+/// The name of the endpoint the whkd running with the whkdrc at `config` listens on
+#[must_use]
+pub fn socket_name(config: &Path) -> String {
+    format!("{}.sock", instance_key(config))
+}
+
+#[derive(Debug, Error)]
+pub enum IpcError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("the connection was closed before a message was received")]
+    Closed,
+    #[error("unexpected response: {0:?}")]
+    Unexpected(Response),
+}
+
+/// A message sent to whkd, serialized as a single line of JSON, e.g. `{"type":"toggle-pause"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Request {
+    Pause,
+    Resume,
+    TogglePause,
+    Reload,
+    Status,
+    Stop,
+    /// Runs the binding for `chord` as if it was pressed, resolving app-specific bindings against
+    /// `app` instead of the active window when it is given
+    Trigger {
+        chord: String,
+        app: Option<String>,
+    },
+    /// Binds `chord` to `command` until whkd exits or `ttl` seconds have passed, failing if the
+    /// chord is already bound
+    Register {
+        chord: String,
+        command: String,
+        ttl: Option<u64>,
+    },
+    /// Binds `chord` to `command` in place of its current binding, failing if the chord is not
+    /// bound
+    Replace {
+        chord: String,
+        command: String,
+        ttl: Option<u64>,
+    },
+    /// Removes the binding for `chord`, whether it was loaded from whkdrc or registered at runtime,
+    /// until whkd exits
+    Unregister {
+        chord: String,
+    },
+    ListBindings,
+    /// Runs `command` with the executor of whkd, without resolving a chord. Its variables have
+    /// already been expanded by the sender, so they are not expanded again.
+    Run {
+        command: String,
+        /// Set in the environment of processes started for the command
+        #[serde(default)]
+        env: Vec<(String, String)>,
+        /// How many milliseconds the command may run for, instead of `.timeout`
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+        #[serde(default)]
+        serial: bool,
+        /// The chord the command is bound to in the sender, which failures of the command are
+        /// reported with, so that they run `.on_error`
+        #[serde(default)]
+        chord: Option<String>,
+    },
+    /// Keeps the connection open after the response and sends an [`Event`] line for everything
+    /// that happens in whkd from then on
+    Subscribe,
+}
+
+/// The reply to a [`Request`], serialized as a single line of JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Response {
+    Ok,
+    Status(Status),
+    Bindings {
+        bindings: Vec<Binding>,
+    },
+    Triggered {
+        command: Option<String>,
+        outcome: Outcome,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// What happened when a chord was triggered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Outcome {
+    /// The command was sent to the shell session
+    Sent,
+    /// The command could not be sent to the shell session
+    Failed { message: String },
+    /// The app block entry for the application is `Ignore`
+    Ignored,
+    /// Nothing is bound to the chord for the application
+    Unbound,
+    /// whkd is paused, so nothing was run
+    Paused,
+    /// The chord is the pause binding, so whkd was paused or resumed
+    PauseToggled { paused: bool },
+}
+
+/// A chord which is currently bound
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Binding {
+    pub chord: String,
+    /// The command run when the chord is pressed outside of any application in its app block
+    pub command: Option<String>,
+    pub app_specific: bool,
+    /// Whether the binding was registered over IPC rather than loaded from whkdrc
+    pub runtime: bool,
+    /// The number of seconds until a runtime binding expires
+    pub expires_in: Option<u64>,
+}
+
+/// Something that happened in whkd, sent to subscribed clients as a single line of JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Event {
+    /// A chord was pressed or triggered and resolved to `command`, which has been handed to the
+    /// executor
+    HotkeyFired {
+        chord: String,
+        app: Option<String>,
+        command: String,
+    },
+    PauseToggled {
+        paused: bool,
+    },
+    ConfigReloaded {
+        config: PathBuf,
+    },
+    /// The shell session exited or stopped accepting commands and was started again
+    ShellRespawned {
+        shell: String,
+    },
+    /// A hotkey could not be registered because whkd already has a hotkey for the same keys
+    RegistrationFailed {
+        chord: String,
+        message: String,
+    },
+    /// A command exited with a non-zero code, timed out or could not be run. `chord` is `None` for
+    /// hooks and commands sent with `whkdc`.
+    CommandFailed {
+        chord: Option<String>,
+        command: String,
+        exit_code: Option<i32>,
+        message: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Status {
+    pub pid: u32,
+    pub paused: bool,
+    pub config: PathBuf,
+    pub shell: String,
+    pub bindings: usize,
+    /// The state of the shell session, for executors which keep one running
+    #[serde(default)]
+    pub session: Option<String>,
+}
+
+#[cfg(windows)]
+fn name(socket: &str) -> std::io::Result<Name<'_>> {
+    socket.to_ns_name::<GenericNamespaced>()
+}
+
+#[cfg(unix)]
+fn name(socket: &str) -> std::io::Result<Name<'_>> {
+    socket_path(socket)?
+        .to_fs_name::<GenericFilePath>()
+        .map(Name::into_owned)
+}
+
+/// The file of the Unix socket for `socket`, in a directory which only its owner can access, so
+/// that other users cannot connect to whkd
+#[cfg(unix)]
+fn socket_path(socket: &str) -> std::io::Result<PathBuf> {
+    use std::os::unix::fs::DirBuilderExt;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime) if !runtime.is_empty() => PathBuf::from(runtime).join("whkd"),
+        _ => std::env::temp_dir().join(match std::env::var("USER") {
+            Ok(user) if !user.is_empty() => format!("whkd-{user}"),
+            _ => String::from("whkd"),
+        }),
+    };
+
+    if let Err(error) = std::fs::DirBuilder::new().mode(0o700).create(&dir) {
+        if error.kind() != std::io::ErrorKind::AlreadyExists {
+            return Err(error);
+        }
+    }
+
+    // only the owner can change the permissions, so this also fails if another user created it
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+
+    Ok(dir.join(socket))
+}
+
+/// Creates the endpoint for `socket`, failing if another process is already listening on it
+pub fn listen(socket: &str) -> std::io::Result<Listener> {
+    let listen = || ListenerOptions::new().name(name(socket)?).create_sync();
+
+    match listen() {
+        #[cfg(unix)]
+        Err(error)
+            if error.kind() == std::io::ErrorKind::AddrInUse
+                && connect(socket)
+                    .is_err_and(|error| error.kind() == std::io::ErrorKind::ConnectionRefused) =>
+        {
+            // left behind by a whkd which did not exit cleanly
+            std::fs::remove_file(socket_path(socket)?)?;
+            listen()
+        }
+        result => result,
+    }
+}
+
+/// Waits for the next client to connect to `listener`
+pub fn accept(listener: &Listener) -> std::io::Result<Stream> {
+    listener.accept()
+}
+
+pub fn connect(socket: &str) -> std::io::Result<Stream> {
+    Stream::connect(name(socket)?)
+}
+
+/// Splits `stream` so that it can be read on one thread while it is written to on another
+#[must_use]
+pub fn split(stream: Stream) -> (RecvHalf, SendHalf) {
+    stream.split()
+}
+
+/// Writes `message` as a single line of JSON
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> Result<(), IpcError> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads a single line of JSON as a message
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> Result<T, IpcError> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(IpcError::Closed);
+    }
+
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Sends `request` to the whkd listening on `socket` and waits for the response
+pub fn send(socket: &str, request: &Request) -> Result<Response, IpcError> {
+    let mut stream = BufReader::new(connect(socket)?);
+    write_message(stream.get_mut(), request)?;
+    read_message(&mut stream)
+}
+
+/// The events sent to a subscribed connection
+pub struct Subscription {
+    stream: BufReader<Stream>,
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Event, IpcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match read_message(&mut self.stream) {
+            Err(IpcError::Closed) => None,
+            result => Some(result),
+        }
+    }
+}
+
+/// Subscribes to the events of the whkd listening on `socket`
+pub fn subscribe(socket: &str) -> Result<Subscription, IpcError> {
+    let mut stream = BufReader::new(connect(socket)?);
+    write_message(stream.get_mut(), &Request::Subscribe)?;
+
+    match read_message(&mut stream)? {
+        Response::Ok => Ok(Subscription { stream }),
+        response => Err(IpcError::Unexpected(response)),
+    }
+}
+
+/// An [`Executor`] which hands commands to the whkd listening on another endpoint
+#[derive(Debug, Clone)]
+pub struct IpcTarget {
+    socket: String,
+}
+
+impl IpcTarget {
+    #[must_use]
+    pub fn new(socket: &str) -> Self {
+        Self {
+            socket: socket.to_string(),
+        }
+    }
+}
+
+impl Executor for IpcTarget {
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError> {
+        let request = Request::Run {
+            command: job.command.clone(),
+            env: job.env.clone(),
+            timeout_ms: job
+                .timeout
+                .map(|timeout| u64::try_from(timeout.as_millis()).unwrap_or(u64::MAX)),
+            serial: job.serial,
+            chord: job.chord.as_ref().map(ToString::to_string),
+        };
+
+        match send(&self.socket, &request)? {
+            Response::Ok => Ok(()),
+            Response::Error { message } => Err(message.into()),
+            response => Err(IpcError::Unexpected(response).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listen_replaces_stale_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let socket = format!("whkd-test-stale-{}.sock", std::process::id());
+        let path = socket_path(&socket).unwrap();
+
+        // bound and closed without removing the file, as if whkd had crashed
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = listen(&socket).unwrap();
+        assert!(connect(&socket).is_ok());
+        assert!(listen(&socket).is_err());
+        assert_eq!(
+            std::fs::metadata(path.parent().unwrap())
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o700
+        );
+
+        drop(listener);
+    }
+
+    #[test]
+    fn test_instance_key() {
+        let config = Path::new("/nonexistent/whkd/whkdrc");
+
+        assert_eq!(instance_key(config), "whkd-fb7f0468f1483390");
+        assert_eq!(
+            instance_key(Path::new("/NonExistent/WHKD/whkdrc")),
+            instance_key(config)
+        );
+        assert_eq!(
+            socket_name(config),
+            format!("{}.sock", instance_key(config))
+        );
+    }
+
+    #[test]
+    fn test_request_format() {
+        assert_eq!(
+            serde_json::to_string(&Request::TogglePause).unwrap(),
+            r#"{"type":"toggle-pause"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"type":"stop"}"#).unwrap(),
+            Request::Stop
+        );
+        assert_eq!(
+            serde_json::to_string(&Event::PauseToggled { paused: true }).unwrap(),
+            r#"{"type":"pause-toggled","paused":true}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"type":"trigger","chord":"alt+h","app":null}"#)
+                .unwrap(),
+            Request::Trigger {
+                chord: String::from("alt+h"),
+                app: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_send() {
+        let socket = format!("whkd-test-{}.sock", std::process::id());
+        let listener = listen(&socket).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let mut stream = BufReader::new(accept(&listener).unwrap());
+            let request: Request = read_message(&mut stream).unwrap();
+            let response = match request {
+                Request::Status => Response::Status(Status {
+                    pid: 1,
+                    paused: true,
+                    config: PathBuf::from("whkdrc"),
+                    shell: String::from("pwsh"),
+                    bindings: 2,
+                    session: Some(String::from("running (pid 42)")),
+                }),
+                _ => Response::Error {
+                    message: String::from("unexpected request"),
+                },
+            };
+
+            write_message(stream.get_mut(), &response).unwrap();
+        });
+
+        let response = send(&socket, &Request::Status).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(
+            response,
+            Response::Status(Status {
+                pid: 1,
+                paused: true,
+                config: PathBuf::from("whkdrc"),
+                shell: String::from("pwsh"),
+                bindings: 2,
+                session: Some(String::from("running (pid 42)")),
+            })
+        );
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let socket = format!("whkd-test-subscribe-{}.sock", std::process::id());
+        let listener = listen(&socket).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let mut stream = BufReader::new(accept(&listener).unwrap());
+            let request: Request = read_message(&mut stream).unwrap();
+            assert_eq!(request, Request::Subscribe);
+
+            write_message(stream.get_mut(), &Response::Ok).unwrap();
+            write_message(stream.get_mut(), &Event::PauseToggled { paused: true }).unwrap();
+            write_message(
+                stream.get_mut(),
+                &Event::ConfigReloaded {
+                    config: PathBuf::from("whkdrc"),
+                },
+            )
+            .unwrap();
+        });
+
+        let events = subscribe(&socket)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        server.join().unwrap();
+
+        assert_eq!(
+            events,
+            vec![
+                Event::PauseToggled { paused: true },
+                Event::ConfigReloaded {
+                    config: PathBuf::from("whkdrc")
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ipc_target() {
+        let socket = format!("whkd-test-target-{}.sock", std::process::id());
+        let listener = listen(&socket).unwrap();
+
+        let server = std::thread::spawn(move || {
+            let mut stream = BufReader::new(accept(&listener).unwrap());
+            let request: Request = read_message(&mut stream).unwrap();
+            write_message(stream.get_mut(), &Response::Ok).unwrap();
+            request
+        });
+
+        IpcTarget::new(&socket)
+            .execute(&Job {
+                env: vec![(String::from("WHKD_CHORD"), String::from("alt + h"))],
+                timeout: Some(Duration::from_millis(1500)),
+                chord: Some("alt + h".parse().unwrap()),
+                ..Job::new("komorebic focus left")
+            })
+            .unwrap();
+
+        assert_eq!(
+            server.join().unwrap(),
+            Request::Run {
+                command: String::from("komorebic focus left"),
+                env: vec![(String::from("WHKD_CHORD"), String::from("alt + h"))],
+                timeout_ms: Some(1500),
+                serial: false,
+                chord: Some(String::from("alt + h")),
+            }
+        );
+    }
+}
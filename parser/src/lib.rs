@@ -1,43 +1,281 @@
+use chumsky::error::SimpleReason;
 use chumsky::prelude::*;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::ops::Range;
+use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 use thiserror::Error;
+use whkd_core::BindingOptions;
+use whkd_core::Diagnostic;
 use whkd_core::HotkeyBinding;
+use whkd_core::LineTerminator;
+use whkd_core::Provenance;
 use whkd_core::Shell;
+use whkd_core::ShellInit;
 use whkd_core::Whkdrc;
 
 #[derive(Debug, Error)]
 pub enum WhkdError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
-    #[error("could not load whkdrc from {0}")]
-    Parse(PathBuf),
+    #[error("could not parse whkdrc:\n{}", format_parse_errors(.0, .1))]
+    Parse(Source, Vec<ParseError>),
+    #[error("invalid whkdrc at {}:\n{}", format_sources(.0), format_diagnostics(.1))]
+    Invalid(Vec<Source>, Vec<Diagnostic>),
+    #[error("no whkdrc was given to load")]
+    NoLayers,
 }
 
-pub fn load(path: &PathBuf) -> Result<Whkdrc, WhkdError> {
+/// Where a layer of whkdrc is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    File(PathBuf),
+    /// A whkdrc which has already been read from standard input
+    Stdin(String),
+}
+
+impl Source {
+    fn read(&self) -> std::io::Result<String> {
+        match self {
+            Self::File(path) => std::fs::read_to_string(path),
+            Self::Stdin(contents) => Ok(contents.clone()),
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Stdin(_) => write!(f, "stdin"),
+        }
+    }
+}
+
+/// A syntax error in a layer of whkdrc
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// The characters of the layer the error is about
+    pub span: Range<usize>,
+    /// The line of the start of `span`, counting from 1
+    pub line: usize,
+    /// The column of the start of `span`, counting from 1
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(contents: &str, error: &Simple<char>) -> Self {
+        let span = error.span();
+        let before = contents.chars().take(span.start).collect::<String>();
+
+        let message = match error.reason() {
+            SimpleReason::Custom(message) => message.clone(),
+            _ => error.to_string(),
+        };
+
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before.chars().rev().take_while(|c| *c != '\n').count() + 1,
+            span,
+            message,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+fn format_parse_errors(source: &Source, errors: &[ParseError]) -> String {
+    errors
+        .iter()
+        .map(|error| format!("{source}:{error}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_sources(sources: &[Source]) -> String {
+    sources
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn format_diagnostics(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn load(path: &Path) -> Result<Whkdrc, WhkdError> {
+    load_layers(&[Source::File(path.to_path_buf())]).map(|(whkdrc, _)| whkdrc)
+}
+
+/// Loads `layers` and merges them in order with [`Whkdrc::merge`], recording which layer each
+/// binding came from. Only the first layer has to set `.shell` and contain bindings.
+pub fn load_layers(layers: &[Source]) -> Result<(Whkdrc, Provenance), WhkdError> {
     use chumsky::Parser;
 
-    let contents = std::fs::read_to_string(path)?;
+    let mut merged: Option<Whkdrc> = None;
+    let mut provenance = Provenance::new();
+
+    for source in layers {
+        let contents = source.read()?;
+
+        let layer = match &merged {
+            None => parser().parse(contents.as_str()),
+            Some(whkdrc) => layer_parser(whkdrc).parse(contents.as_str()),
+        }
+        .map_err(|errors| {
+            WhkdError::Parse(
+                source.clone(),
+                errors
+                    .iter()
+                    .map(|error| ParseError::new(&contents, error))
+                    .collect(),
+            )
+        })?;
+
+        tracing::debug!(
+            source = %source,
+            shell = %layer.shell,
+            bindings = layer.bindings.len(),
+            app_blocks = layer.app_bindings.len(),
+            "parsed whkdrc layer"
+        );
 
-    parser()
-        .parse(contents)
-        .map_err(|_error| WhkdError::Parse(path.clone()))
+        provenance.record(&source.to_string(), &layer);
+
+        match &mut merged {
+            None => merged = Some(layer),
+            Some(whkdrc) => whkdrc.merge(layer),
+        }
+    }
+
+    let whkdrc = merged.ok_or(WhkdError::NoLayers)?;
+
+    let errors = whkdrc
+        .validate()
+        .into_iter()
+        .filter(Diagnostic::is_error)
+        .collect::<Vec<_>>();
+
+    if !errors.is_empty() {
+        return Err(WhkdError::Invalid(layers.to_vec(), errors));
+    }
+
+    Ok((whkdrc, provenance))
+}
+
+/// A directive at the top of whkdrc, which can be given in any order but at most once
+#[derive(Debug, Clone)]
+enum Directive {
+    Shell(Shell, Vec<String>),
+    ShellInit(ShellInit),
+    Echo(bool),
+    Pause(Vec<String>),
+    PauseHook(String),
+    ExitHook(String),
+    Timeout(Duration),
+    OnError(String),
+}
+
+impl Directive {
+    const fn name(&self) -> &'static str {
+        match self {
+            Self::Shell(..) => ".shell",
+            Self::ShellInit(_) => ".shell_init",
+            Self::Echo(_) => ".echo",
+            Self::Pause(_) => ".pause",
+            Self::PauseHook(_) => ".pause_hook",
+            Self::ExitHook(_) => ".exit_hook",
+            Self::Timeout(_) => ".timeout",
+            Self::OnError(_) => ".on_error",
+        }
+    }
+}
+
+/// A single entry in the list of options before the command of a binding
+#[derive(Debug, Clone)]
+enum BindingOption {
+    Timeout(Duration),
+    Serial,
+    Shell(Shell),
 }
 
-#[allow(clippy::too_many_lines)]
 #[must_use]
 pub fn parser() -> impl Parser<char, Whkdrc, Error = Simple<char>> {
+    grammar(None)
+}
+
+/// Parses a whkdrc which is merged on top of `base`. `.shell` can be left out to keep using the
+/// shell of `base` and its arguments, and there do not have to be any bindings.
+#[must_use]
+pub fn layer_parser(base: &Whkdrc) -> impl Parser<char, Whkdrc, Error = Simple<char>> {
+    grammar(Some((base.shell.clone(), base.shell_args.clone())))
+}
+
+#[allow(clippy::too_many_lines)]
+fn grammar(
+    default_shell: Option<(Shell, Vec<String>)>,
+) -> impl Parser<char, Whkdrc, Error = Simple<char>> {
     let comment = just::<_, _, Simple<char>>("#")
         .then(take_until(text::newline()))
         .padded()
         .ignored();
 
-    let shell = just(".shell")
+    let inline_whitespace = one_of(" \t").repeated();
+
+    // a shell argument, in double quotes if it contains spaces
+    let word = choice((
+        just('"')
+            .ignore_then(filter(|c: &char| *c != '"' && *c != '\n').repeated())
+            .then_ignore(just('"')),
+        filter(|c: &char| !c.is_whitespace() && *c != '#' && *c != '"')
+            .repeated()
+            .at_least(1),
+    ))
+    .collect::<String>();
+
+    let terminator = just("[")
+        .ignore_then(choice((
+            just("crlf").to(LineTerminator::Crlf),
+            just("lf").to(LineTerminator::Lf),
+        )))
+        .then_ignore(just("]"))
+        .then_ignore(inline_whitespace.clone());
+
+    let custom_shell = just("custom")
+        .ignore_then(inline_whitespace.clone())
+        .ignore_then(terminator.or_not())
+        .then(word)
+        .map(|(terminator, program)| Shell::Custom {
+            program,
+            terminator: terminator.unwrap_or_default(),
+        });
+
+    let named_shell = text::ident().try_map(|name: String, span| {
+        Shell::from_str(&name).map_err(|error| Simple::custom(span, error.to_string()))
+    });
+
+    let requires_bindings = default_shell.is_none();
+
+    // a keyword rather than the text, so that `.shell_init` is not taken for `.shell`
+    let shell = just('.')
+        .ignore_then(text::keyword("shell"))
         .padded()
-        .ignore_then(choice((just("pwsh"), just("powershell"), just("cmd"))))
-        .repeated()
-        .exactly(1)
-        .collect::<String>()
-        .map(Shell::from);
+        .ignore_then(choice((custom_shell, named_shell)))
+        .then(inline_whitespace.clone().ignore_then(word).repeated())
+        .map(|(shell, args)| Directive::Shell(shell, args));
 
     let hotkeys = choice((text::ident(), text::int(10)))
         .padded()
@@ -50,20 +288,165 @@ pub fn parser() -> impl Parser<char, Whkdrc, Error = Simple<char>> {
         .map(|c| c.0)
         .collect::<String>();
 
-    let pause = just(".pause")
+    // a block of commands in square brackets, one per line, or a single command
+    let shell_init = just(".shell_init")
+        .padded()
+        .ignore_then(
+            just("[replace]")
+                .then(inline_whitespace.clone())
+                .or_not()
+                .map(|replace| replace.is_some()),
+        )
+        .then(choice((
+            just("[")
+                .then(inline_whitespace)
+                .then(text::newline())
+                .ignore_then(
+                    command
+                        .clone()
+                        .try_map(|command, span| {
+                            if command.is_empty() || command.trim() == "]" {
+                                Err(Simple::custom(span, "end of .shell_init block"))
+                            } else {
+                                Ok(command)
+                            }
+                        })
+                        .padded_by(comment.repeated())
+                        .repeated(),
+                )
+                .then_ignore(just("]").padded()),
+            command.clone().map(|command| vec![command]),
+        )))
+        .map(|(replace, commands)| Directive::ShellInit(ShellInit { commands, replace }));
+
+    let echo = just(".echo")
+        .padded()
+        .ignore_then(choice((just("on").to(true), just("off").to(false))))
+        .map(Directive::Echo);
+
+    let pause = just('.')
+        .ignore_then(text::keyword("pause"))
         .padded()
         .ignore_then(hotkeys)
-        .padded_by(comment.repeated())
-        .or_not();
+        .map(Directive::Pause);
 
     let pause_hook = just(".pause_hook")
         .padded()
         .ignore_then(command.clone())
+        .map(Directive::PauseHook);
+
+    let exit_hook = just(".exit_hook")
+        .padded()
+        .ignore_then(command.clone())
+        .map(Directive::ExitHook);
+
+    let duration = text::int(10)
+        .then(choice((just("ms"), just("s"), just("m"))))
+        .try_map(|(value, unit), span| {
+            whkd_core::parse_duration(&format!("{value}{unit}"))
+                .ok_or_else(|| Simple::custom(span, "invalid duration"))
+        });
+
+    let timeout = just(".timeout")
+        .padded()
+        .ignore_then(duration)
+        .map(Directive::Timeout);
+
+    let on_error = just(".on_error")
+        .padded()
+        .ignore_then(command.clone())
+        .map(Directive::OnError);
+
+    // the whitespace before each directive is left out of its span, so errors point at its name
+    let directives = text::whitespace()
+        .ignore_then(
+            choice((
+                shell, shell_init, echo, pause, pause_hook, exit_hook, timeout, on_error,
+            ))
+            .map_with_span(|directive, span| (directive, span)),
+        )
         .padded_by(comment.repeated())
-        .or_not();
+        .repeated()
+        .try_map(move |directives, span| {
+            let mut whkdrc = Whkdrc {
+                shell: Shell::None,
+                shell_args: vec![],
+                shell_init: None,
+                echo: None,
+                app_bindings: vec![],
+                bindings: vec![],
+                pause_binding: None,
+                pause_hook: None,
+                exit_hook: None,
+                timeout: None,
+                on_error: None,
+            };
+
+            let mut shell = None;
+            let mut seen = vec![];
+            for (directive, span) in directives {
+                if seen.contains(&directive.name()) {
+                    return Err(Simple::custom(
+                        span,
+                        format!("{} can only be given once", directive.name()),
+                    ));
+                }
+
+                seen.push(directive.name());
+
+                match directive {
+                    Directive::Shell(program, args) => shell = Some((program, args)),
+                    Directive::ShellInit(init) => whkdrc.shell_init = Some(init),
+                    Directive::Echo(echo) => whkdrc.echo = Some(echo),
+                    Directive::Pause(keys) => whkdrc.pause_binding = Some(keys),
+                    Directive::PauseHook(hook) => whkdrc.pause_hook = Some(hook),
+                    Directive::ExitHook(hook) => whkdrc.exit_hook = Some(hook),
+                    Directive::Timeout(timeout) => whkdrc.timeout = Some(timeout),
+                    Directive::OnError(hook) => whkdrc.on_error = Some(hook),
+                }
+            }
+
+            (whkdrc.shell, whkdrc.shell_args) = shell
+                .or_else(|| default_shell.clone())
+                .ok_or_else(|| Simple::custom(span, "missing .shell directive"))?;
+
+            Ok(whkdrc)
+        });
 
     let delimiter = just(":").padded();
 
+    let option = choice((
+        just("timeout")
+            .padded()
+            .ignore_then(just("=").padded())
+            .ignore_then(duration)
+            .map(BindingOption::Timeout),
+        just("serial").to(BindingOption::Serial),
+        text::ident().try_map(|name: String, span| {
+            Shell::from_str(&name)
+                .map(BindingOption::Shell)
+                .map_err(|error| Simple::custom(span, error.to_string()))
+        }),
+    ))
+    .padded();
+
+    let options = just("[")
+        .ignore_then(option.separated_by(just(",")).at_least(1))
+        .then_ignore(just("]"))
+        .or_not()
+        .map(|list| {
+            let mut options = BindingOptions::default();
+            for option in list.unwrap_or_default() {
+                match option {
+                    BindingOption::Timeout(timeout) => options.timeout = Some(timeout),
+                    BindingOption::Serial => options.serial = true,
+                    BindingOption::Shell(shell) => options.shell = Some(shell),
+                }
+            }
+
+            options
+        });
+
     let default_keyword = just("Default").padded();
     let ignore_keyword = just("Ignore").padded();
 
@@ -76,10 +459,10 @@ pub fn parser() -> impl Parser<char, Whkdrc, Error = Simple<char>> {
             .map(|a| a.join(" ")),
     ));
 
-    let process_command = choice((
+    let process_command = options.clone().then(choice((
         ignore_keyword.map(|_| String::from("Ignore")),
         command.clone(),
-    ));
+    )));
 
     let process_mapping = process_name
         .then_ignore(delimiter)
@@ -94,25 +477,27 @@ pub fn parser() -> impl Parser<char, Whkdrc, Error = Simple<char>> {
         .padded()
         .padded_by(comment.repeated())
         .then_ignore(just("]"))
-        .collect::<Vec<(String, String)>>();
+        .collect::<Vec<(String, (BindingOptions, String))>>();
 
-    let binding = hotkeys.then_ignore(delimiter).then(command);
-    let process_bindings = hotkeys.then(process_command_map);
+    let binding = hotkeys
+        .then_ignore(delimiter)
+        .then(options.clone())
+        .then(command);
+    let process_bindings = hotkeys.then(options).then(process_command_map);
 
     comment
         .repeated()
-        .ignore_then(shell)
-        .then(pause)
-        .then(pause_hook)
+        .ignore_then(directives)
         .then(
             process_bindings
-                .map(|(keys, apps_commands)| {
+                .map(|((keys, block_options), apps_commands)| {
                     let mut collected = vec![];
-                    for (app, command) in apps_commands {
+                    for (app, (options, command)) in apps_commands {
                         collected.push(HotkeyBinding {
                             keys: keys.clone(),
                             command,
                             process_name: Option::from(app),
+                            options: options.or(&block_options),
                         });
                     }
 
@@ -125,25 +510,22 @@ pub fn parser() -> impl Parser<char, Whkdrc, Error = Simple<char>> {
         )
         .then(
             binding
-                .map(|(keys, command)| HotkeyBinding {
+                .map(|((keys, options), command)| HotkeyBinding {
                     keys,
                     command,
                     process_name: None,
+                    options,
                 })
                 .padded()
                 .padded_by(comment.repeated())
                 .repeated()
-                .at_least(1),
-        )
-        .map(
-            |((((shell, pause_binding), pause_hook), app_bindings), bindings)| Whkdrc {
-                shell,
-                app_bindings,
-                bindings,
-                pause_binding,
-                pause_hook,
-            },
+                .at_least(usize::from(requires_bindings)),
         )
+        .map(|((whkdrc, app_bindings), bindings)| Whkdrc {
+            app_bindings,
+            bindings,
+            ..whkdrc
+        })
 }
 
 #[cfg(test)]
@@ -160,14 +542,21 @@ alt + h : echo "Hello""#;
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![],
             bindings: vec![HotkeyBinding {
                 keys: vec![String::from("alt"), String::from("h")],
                 command: String::from("echo \"Hello\""),
                 process_name: None,
+                options: BindingOptions::default(),
             }],
             pause_binding: None,
             pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
@@ -184,14 +573,21 @@ alt + h : echo "Hello""#;
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![],
             bindings: vec![HotkeyBinding {
                 keys: vec![String::from("alt"), String::from("h")],
                 command: String::from("echo \"Hello\""),
                 process_name: None,
+                options: BindingOptions::default(),
             }],
             pause_binding: None,
             pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
@@ -229,6 +625,9 @@ alt + 1 : komorebic focus-workspace 0 # digits are fine in the hotkeys section
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Cmd,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![(
                 vec![String::from("alt"), String::from("n")],
                 vec![
@@ -236,11 +635,13 @@ alt + 1 : komorebic focus-workspace 0 # digits are fine in the hotkeys section
                         keys: vec![String::from("alt"), String::from("n")],
                         command: String::from(r#"echo "hello firefox""#),
                         process_name: Option::from("Firefox".to_string()),
+                        options: BindingOptions::default(),
                     },
                     HotkeyBinding {
                         keys: vec![String::from("alt"), String::from("n")],
                         command: String::from(r#"echo "hello chrome""#),
                         process_name: Option::from("Google Chrome".to_string()),
+                        options: BindingOptions::default(),
                     },
                 ],
             )],
@@ -249,30 +650,38 @@ alt + 1 : komorebic focus-workspace 0 # digits are fine in the hotkeys section
                     keys: vec![String::from("alt"), String::from("h")],
                     command: String::from("komorebic focus left"),
                     process_name: None,
+                    options: BindingOptions::default(),
                 },
                 HotkeyBinding {
                     keys: vec![String::from("alt"), String::from("j")],
                     command: String::from("komorebic focus down"),
                     process_name: None,
+                    options: BindingOptions::default(),
                 },
                 HotkeyBinding {
                     keys: vec![String::from("alt"), String::from("k")],
                     command: String::from("komorebic focus up"),
                     process_name: None,
+                    options: BindingOptions::default(),
                 },
                 HotkeyBinding {
                     keys: vec![String::from("alt"), String::from("l")],
                     command: String::from("komorebic focus right"),
                     process_name: None,
+                    options: BindingOptions::default(),
                 },
                 HotkeyBinding {
                     keys: vec![String::from("alt"), String::from("1")],
                     command: String::from("komorebic focus-workspace 0"),
                     process_name: None,
+                    options: BindingOptions::default(),
                 },
             ],
             pause_binding: None,
             pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
@@ -289,14 +698,21 @@ f11 : echo "hello f11""#;
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![],
             bindings: vec![HotkeyBinding {
                 keys: vec![String::from("f11")],
                 command: String::from("echo \"hello f11\""),
                 process_name: None,
+                options: BindingOptions::default(),
             }],
             pause_binding: None,
             pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
@@ -319,6 +735,9 @@ alt + h : echo "Hello""#;
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![(
                 vec![String::from("alt"), String::from("n")],
                 vec![
@@ -326,21 +745,25 @@ alt + h : echo "Hello""#;
                         keys: vec![String::from("alt"), String::from("n")],
                         command: String::from(r#"echo "hello world""#),
                         process_name: Option::from("Default".to_string()),
+                        options: BindingOptions::default(),
                     },
                     HotkeyBinding {
                         keys: vec![String::from("alt"), String::from("n")],
                         command: String::from(r#"echo "hello firefox""#),
                         process_name: Option::from("Firefox".to_string()),
+                        options: BindingOptions::default(),
                     },
                     HotkeyBinding {
                         keys: vec![String::from("alt"), String::from("n")],
                         command: String::from(r#"echo "hello chrome""#),
                         process_name: Option::from("Google Chrome".to_string()),
+                        options: BindingOptions::default(),
                     },
                     HotkeyBinding {
                         keys: vec![String::from("alt"), String::from("n")],
                         command: String::from("Ignore"),
                         process_name: Option::from("Zen Browser".to_string()),
+                        options: BindingOptions::default(),
                     },
                 ],
             )],
@@ -348,9 +771,13 @@ alt + h : echo "Hello""#;
                 keys: vec![String::from("alt"), String::from("h")],
                 command: String::from(r#"echo "Hello""#),
                 process_name: None,
+                options: BindingOptions::default(),
             }],
             pause_binding: None,
             pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
@@ -367,11 +794,15 @@ alt + h : echo "Hello""#;
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![],
             bindings: vec![HotkeyBinding {
                 keys: vec![String::from("alt"), String::from("h")],
                 command: String::from(r#"echo "Hello""#),
                 process_name: None,
+                options: BindingOptions::default(),
             }],
             pause_binding: Some(vec![
                 "ctrl".to_string(),
@@ -379,6 +810,9 @@ alt + h : echo "Hello""#;
                 "esc".to_string(),
             ]),
             pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
@@ -396,11 +830,15 @@ alt + h : echo "Hello""#;
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![],
             bindings: vec![HotkeyBinding {
                 keys: vec![String::from("alt"), String::from("h")],
                 command: String::from(r#"echo "Hello""#),
                 process_name: None,
+                options: BindingOptions::default(),
             }],
             pause_binding: Some(vec![
                 "ctrl".to_string(),
@@ -408,6 +846,9 @@ alt + h : echo "Hello""#;
                 "esc".to_string(),
             ]),
             pause_hook: Some("komorebic toggle-pause".to_string()),
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
@@ -425,11 +866,15 @@ alt + h : echo "Hello""#;
         let output = parser().parse(src);
         let expected = Whkdrc {
             shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
             app_bindings: vec![],
             bindings: vec![HotkeyBinding {
                 keys: vec![String::from("alt"), String::from("h")],
                 command: String::from(r#"echo "Hello""#),
                 process_name: None,
+                options: BindingOptions::default(),
             }],
             pause_binding: Some(vec![
                 "alt".to_string(),
@@ -437,8 +882,422 @@ alt + h : echo "Hello""#;
                 "p".to_string(),
             ]),
             pause_hook: Some("komorebic toggle-pause".to_string()),
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
         };
 
         assert_eq!(output.unwrap(), expected);
     }
+
+    #[test]
+    fn test_exit_hook() {
+        let src = r#"
+.shell pwsh
+.pause alt + shift + p
+.pause_hook komorebic toggle-pause
+.exit_hook komorebic stop # run when whkd shuts down
+
+alt + h : echo "Hello""#;
+
+        let output = parser().parse(src);
+        let expected = Whkdrc {
+            shell: Shell::Pwsh,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
+            app_bindings: vec![],
+            bindings: vec![HotkeyBinding {
+                keys: vec![String::from("alt"), String::from("h")],
+                command: String::from(r#"echo "Hello""#),
+                process_name: None,
+                options: BindingOptions::default(),
+            }],
+            pause_binding: Some(vec![
+                "alt".to_string(),
+                "shift".to_string(),
+                "p".to_string(),
+            ]),
+            pause_hook: Some("komorebic toggle-pause".to_string()),
+            exit_hook: Some("komorebic stop".to_string()),
+            timeout: None,
+            on_error: None,
+        };
+
+        assert_eq!(output.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_layer_without_shell() {
+        let src = r#"
+# machine-local overrides
+alt + n [
+    Firefox : Unbind
+]
+
+alt + h : Unbind"#;
+
+        let output = layer_parser(&Whkdrc::builder(Shell::Cmd).build().unwrap()).parse(src);
+        let expected = Whkdrc {
+            shell: Shell::Cmd,
+            shell_args: vec![],
+            shell_init: None,
+            echo: None,
+            app_bindings: vec![(
+                vec![String::from("alt"), String::from("n")],
+                vec![HotkeyBinding {
+                    keys: vec![String::from("alt"), String::from("n")],
+                    command: String::from("Unbind"),
+                    process_name: Some(String::from("Firefox")),
+                    options: BindingOptions::default(),
+                }],
+            )],
+            bindings: vec![HotkeyBinding {
+                keys: vec![String::from("alt"), String::from("h")],
+                command: String::from("Unbind"),
+                process_name: None,
+                options: BindingOptions::default(),
+            }],
+            pause_binding: None,
+            pause_hook: None,
+            exit_hook: None,
+            timeout: None,
+            on_error: None,
+        };
+
+        assert_eq!(output.unwrap(), expected);
+        assert!(parser().parse(src).is_err());
+    }
+
+    #[test]
+    fn test_load_layers() {
+        let base = Source::Stdin(String::from(
+            r#"
+.shell cmd
+alt + h : echo left
+alt + l : echo right"#,
+        ));
+        let local = Source::Stdin(String::from(
+            r#"
+alt + h : echo local
+alt + l : Unbind"#,
+        ));
+
+        let (whkdrc, provenance) = load_layers(&[base, local]).unwrap();
+
+        assert_eq!(whkdrc.shell, Shell::Cmd);
+        assert_eq!(
+            whkdrc.bindings,
+            vec![HotkeyBinding {
+                keys: vec![String::from("alt"), String::from("h")],
+                command: String::from("echo local"),
+                process_name: None,
+                options: BindingOptions::default(),
+            }]
+        );
+        assert_eq!(provenance.source(&whkdrc.bindings[0]), Some("stdin"));
+        assert!(matches!(load_layers(&[]), Err(WhkdError::NoLayers)));
+    }
+
+    #[test]
+    fn test_shell_none() {
+        let src = r#"
+.shell none
+alt + h : komorebic focus left"#;
+
+        let output = parser().parse(src).unwrap();
+
+        assert_eq!(output.shell, Shell::None);
+        assert_eq!(output.bindings[0].command, "komorebic focus left");
+    }
+
+    #[test]
+    fn test_binding_options() {
+        let src = r#"
+.shell pwsh
+.timeout 30s # the default for every command
+
+alt + n [
+    Firefox : [timeout=500ms] echo firefox
+    Default : echo default
+]
+
+alt + h : [ timeout = 2m, serial ] komorebic focus left
+alt + b : [System.Console]::Beep()"#;
+
+        let output = parser().parse(src).unwrap();
+
+        assert_eq!(output.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(
+            output.app_bindings[0].1[0].options.timeout,
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(output.app_bindings[0].1[1].options.timeout, None);
+        assert_eq!(
+            output.bindings[0],
+            HotkeyBinding {
+                keys: vec![String::from("alt"), String::from("h")],
+                command: String::from("komorebic focus left"),
+                process_name: None,
+                options: BindingOptions {
+                    timeout: Some(Duration::from_secs(120)),
+                    serial: true,
+                    shell: None,
+                },
+            }
+        );
+        assert_eq!(output.bindings[1].command, "[System.Console]::Beep()");
+        assert_eq!(output.bindings[1].options, BindingOptions::default());
+    }
+
+    #[test]
+    fn test_on_error() {
+        let src = r#"
+.shell pwsh
+.timeout 10s
+.on_error komorebic notify "${WHKD_CHORD} failed" # runs when a bound command fails
+
+alt + h : komorebic focus left"#;
+
+        let output = parser().parse(src).unwrap();
+
+        assert_eq!(
+            output.on_error.as_deref(),
+            Some(r#"komorebic notify "${WHKD_CHORD} failed""#)
+        );
+        assert_eq!(output.bindings.len(), 1);
+    }
+
+    #[test]
+    fn test_shell_options() {
+        let src = r#"
+.shell pwsh
+
+alt + n [cmd, timeout=5s] [
+    Firefox : [pwsh] echo firefox
+    Default : dir
+]
+
+alt + x : [cmd] dir"#;
+
+        let output = parser().parse(src).unwrap();
+        let entries = &output.app_bindings[0].1;
+
+        assert_eq!(entries[0].options.shell, Some(Shell::Pwsh));
+        assert_eq!(entries[0].options.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(entries[1].options.shell, Some(Shell::Cmd));
+        assert_eq!(output.bindings[0].options.shell, Some(Shell::Cmd));
+        assert_eq!(output.bindings[0].command, "dir");
+    }
+
+    #[test]
+    fn test_custom_shells() {
+        let output = parser()
+            .parse(".shell pwsh -NoProfile -ExecutionPolicy Bypass # no profile\nalt + h : ls")
+            .unwrap();
+        assert_eq!(output.shell, Shell::Pwsh);
+        assert_eq!(
+            output.shell_args,
+            vec!["-NoProfile", "-ExecutionPolicy", "Bypass"]
+        );
+
+        let output = parser()
+            .parse(".shell custom [crlf] \"C:\\Program Files\\xonsh\\xonsh.exe\" --no-rc\nalt + h : ls")
+            .unwrap();
+        assert_eq!(
+            output.shell,
+            Shell::Custom {
+                program: String::from(r"C:\Program Files\xonsh\xonsh.exe"),
+                terminator: LineTerminator::Crlf,
+            }
+        );
+        assert_eq!(output.shell_args, vec!["--no-rc"]);
+
+        assert_eq!(
+            parser().parse(".shell wsl\nalt + h : ls").unwrap().shell,
+            Shell::Wsl
+        );
+        assert!(parser().parse(".shell fish\nalt + h : ls").is_err());
+    }
+
+    #[test]
+    fn test_shell_init() {
+        let src = r#"
+.shell pwsh
+.shell_init Import-Module komorebi # once per session
+.pause alt + p
+
+alt + h : ls"#;
+
+        let output = parser().parse(src).unwrap();
+        assert_eq!(
+            output.shell_init,
+            Some(ShellInit {
+                commands: vec![String::from("Import-Module komorebi")],
+                replace: false,
+            })
+        );
+        assert_eq!(
+            output.pause_binding,
+            Some(vec![String::from("alt"), String::from("p")])
+        );
+
+        let src = r#"
+.shell pwsh
+.shell_init [replace] [
+    # set up the session
+    Import-Module komorebi
+    Set-Alias k komorebic
+
+]
+
+alt + h : ls"#;
+
+        let output = parser().parse(src).unwrap();
+        assert_eq!(
+            output.shell_init,
+            Some(ShellInit {
+                commands: vec![
+                    String::from("Import-Module komorebi"),
+                    String::from("Set-Alias k komorebic"),
+                ],
+                replace: true,
+            })
+        );
+
+        let output = parser()
+            .parse(".shell pwsh\n.shell_init [System.Console]::Beep()\nalt + h : ls")
+            .unwrap();
+        assert_eq!(
+            output.shell_init.map(|init| init.commands),
+            Some(vec![String::from("[System.Console]::Beep()")])
+        );
+
+        let base = Whkdrc::builder(Shell::Cmd).build().unwrap();
+        let output = layer_parser(&base)
+            .parse(".shell_init doskey ls=dir")
+            .unwrap();
+        assert_eq!(output.shell, Shell::Cmd);
+        assert_eq!(
+            output.shell_init.map(|init| init.commands),
+            Some(vec![String::from("doskey ls=dir")])
+        );
+    }
+
+    #[test]
+    fn test_echo() {
+        let output = parser()
+            .parse(
+                ".shell cmd
+.echo on # show every command
+alt + h : dir",
+            )
+            .unwrap();
+        assert_eq!(output.echo, Some(true));
+
+        let output = parser()
+            .parse(
+                ".shell pwsh
+.echo off
+alt + h : ls",
+            )
+            .unwrap();
+        assert_eq!(output.echo, Some(false));
+
+        let output = parser()
+            .parse(
+                ".shell pwsh
+alt + h : ls",
+            )
+            .unwrap();
+        assert_eq!(output.echo, None);
+
+        assert!(parser()
+            .parse(
+                ".shell pwsh
+.echo yes
+alt + h : ls"
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_directives_in_any_order() {
+        let src = r#"
+.on_error komorebic notify failed
+.pause_hook komorebic toggle-pause # runs when paused or resumed
+.timeout 5s
+.echo on
+.shell_init doskey ls=dir
+.pause alt + p
+.shell cmd
+
+alt + h : dir"#;
+
+        let output = parser().parse(src).unwrap();
+
+        assert_eq!(output.shell, Shell::Cmd);
+        assert_eq!(
+            output.shell_init.map(|init| init.commands),
+            Some(vec![String::from("doskey ls=dir")])
+        );
+        assert_eq!(output.echo, Some(true));
+        assert_eq!(
+            output.pause_binding,
+            Some(vec![String::from("alt"), String::from("p")])
+        );
+        assert_eq!(output.pause_hook.as_deref(), Some("komorebic toggle-pause"));
+        assert_eq!(output.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(output.on_error.as_deref(), Some("komorebic notify failed"));
+
+        let output = layer_parser(&Whkdrc::builder(Shell::Pwsh).build().unwrap())
+            .parse(".exit_hook echo bye\n.pause_hook echo paused")
+            .unwrap();
+        assert_eq!(output.shell, Shell::Pwsh);
+        assert_eq!(output.pause_binding, None);
+        assert_eq!(output.pause_hook.as_deref(), Some("echo paused"));
+    }
+
+    #[test]
+    fn test_duplicate_directives() {
+        let errors = parser()
+            .parse(
+                ".shell pwsh
+.timeout 5s
+.echo on
+.timeout 10s
+alt + h : ls",
+            )
+            .unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].reason(),
+            &SimpleReason::Custom(String::from(".timeout can only be given once"))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let source = Source::Stdin(String::from(
+            ".shell pwsh
+.timeout 5s
+.timeout 10s
+alt + h : ls",
+        ));
+
+        let Err(WhkdError::Parse(_, errors)) = load_layers(&[source]) else {
+            panic!("expected a parse error");
+        };
+
+        assert_eq!(
+            errors,
+            vec![ParseError {
+                span: 24..36,
+                line: 3,
+                column: 1,
+                message: String::from(".timeout can only be given once"),
+            }]
+        );
+    }
 }
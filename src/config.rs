@@ -0,0 +1,247 @@
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::path::Path;
+use std::path::PathBuf;
+use whkd_core::Provenance;
+use whkd_core::Whkdrc;
+use whkd_parser::Source;
+use whkd_parser::WhkdError;
+
+/// A place whkdrc was looked for
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub origin: &'static str,
+    pub path: PathBuf,
+}
+
+/// No whkdrc was found in any of the places it is looked for
+#[derive(Debug)]
+pub struct NotFound {
+    pub tried: Vec<Candidate>,
+}
+
+impl Display for NotFound {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "could not find whkdrc, tried:")?;
+
+        for candidate in &self.tried {
+            write!(f, "\n  {} ({})", candidate.path.display(), candidate.origin)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for NotFound {}
+
+/// The origin of the whkdrc in the directory given with `WHKD_CONFIG_HOME`
+const CONFIG_HOME: &str = "$WHKD_CONFIG_HOME";
+
+/// The places whkdrc is looked for when `--config` is not given, in order of precedence
+#[must_use]
+pub fn candidates() -> Vec<Candidate> {
+    let mut candidates = vec![];
+
+    if let Some(home) = std::env::var_os("WHKD_CONFIG_HOME") {
+        candidates.push(Candidate {
+            origin: CONFIG_HOME,
+            path: PathBuf::from(home).join("whkdrc"),
+        });
+    }
+
+    if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME") {
+        candidates.push(Candidate {
+            origin: "$XDG_CONFIG_HOME",
+            path: PathBuf::from(xdg).join("whkd").join("whkdrc"),
+        });
+    }
+
+    if let Some(home) = dirs::home_dir() {
+        candidates.push(Candidate {
+            origin: "home directory",
+            path: home.join(".config").join("whkdrc"),
+        });
+    }
+
+    if let Some(appdata) = std::env::var_os("APPDATA") {
+        candidates.push(Candidate {
+            origin: "%APPDATA%",
+            path: PathBuf::from(appdata).join("whkd").join("whkdrc"),
+        });
+    }
+
+    if let Some(dir) = std::env::current_exe()
+        .ok()
+        .as_deref()
+        .and_then(Path::parent)
+    {
+        candidates.push(Candidate {
+            origin: "next to the executable",
+            path: dir.join("whkdrc"),
+        });
+    }
+
+    candidates
+}
+
+/// Finds the whkdrc to use, which is the first existing file from [`candidates`]. When
+/// `WHKD_CONFIG_HOME` is set, its whkdrc is the only one which is looked for.
+pub fn discover() -> Result<PathBuf, NotFound> {
+    let mut candidates = candidates();
+
+    // a directory chosen explicitly is not silently passed over for another whkdrc
+    if candidates
+        .first()
+        .is_some_and(|candidate| candidate.origin == CONFIG_HOME)
+    {
+        candidates.truncate(1);
+    }
+
+    candidates
+        .iter()
+        .find(|candidate| candidate.path.is_file())
+        .map(|candidate| candidate.path.clone())
+        .ok_or(NotFound { tried: candidates })
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    source: Source,
+    /// Whether the layer is skipped when its file does not exist
+    optional: bool,
+}
+
+/// The sources merged to make up the configuration, in order
+#[derive(Debug, Clone)]
+pub struct Layers {
+    layers: Vec<Layer>,
+}
+
+impl Layers {
+    /// A system-wide whkdrc shared by every user, the user's whkdrc, and a `whkdrc.local` next to
+    /// it for machine-specific changes
+    pub fn discovered(user: PathBuf) -> Self {
+        let mut local = OsString::from(user.as_os_str());
+        local.push(".local");
+
+        let system = std::env::var_os("PROGRAMDATA")
+            .map(|data| PathBuf::from(data).join("whkd").join("whkdrc"));
+
+        let mut layers = vec![];
+
+        if let Some(system) = system {
+            layers.push(Layer {
+                source: Source::File(system),
+                optional: true,
+            });
+        }
+
+        layers.push(Layer {
+            source: Source::File(user),
+            optional: false,
+        });
+
+        layers.push(Layer {
+            source: Source::File(PathBuf::from(local)),
+            optional: true,
+        });
+
+        Self { layers }
+    }
+
+    /// Exactly the files given with `--config`, where `-` reads whkdrc from stdin. Environment
+    /// variables in the paths are expanded in the same way as in commands.
+    pub fn given(configs: &[PathBuf]) -> Result<Self> {
+        let mut layers = vec![];
+        let mut stdin = false;
+
+        for config in configs {
+            let source = if config.as_os_str() == "-" {
+                if stdin {
+                    return Err(eyre!("whkdrc can only be read from stdin once"));
+                }
+
+                stdin = true;
+                Source::Stdin(std::io::read_to_string(std::io::stdin())?)
+            } else {
+                let path = PathBuf::from(whkd_core::expand_env(&config.to_string_lossy())?);
+                if !path.is_file() {
+                    return Err(NotFound {
+                        tried: vec![Candidate {
+                            origin: "--config",
+                            path,
+                        }],
+                    }
+                    .into());
+                }
+
+                Source::File(path)
+            };
+
+            layers.push(Layer {
+                source,
+                optional: false,
+            });
+        }
+
+        Ok(Self { layers })
+    }
+
+    /// The first layer which is always loaded, identifying this configuration. whkdrc read from
+    /// stdin is identified as `-`.
+    pub fn primary(&self) -> PathBuf {
+        self.layers
+            .iter()
+            .find(|layer| !layer.optional)
+            .map_or_else(PathBuf::new, |layer| match &layer.source {
+                Source::File(path) => path.clone(),
+                Source::Stdin(_) => PathBuf::from("-"),
+            })
+    }
+
+    /// The files of every layer, whether they exist or not
+    pub fn paths(&self) -> Vec<&Path> {
+        self.layers
+            .iter()
+            .filter_map(|layer| match &layer.source {
+                Source::File(path) => Some(path.as_path()),
+                Source::Stdin(_) => None,
+            })
+            .collect()
+    }
+
+    /// The layers which are loaded, skipping optional layers which do not exist
+    pub fn sources(&self) -> Vec<Source> {
+        self.layers
+            .iter()
+            .filter(|layer| match &layer.source {
+                Source::File(path) => !layer.optional || path.is_file(),
+                Source::Stdin(_) => true,
+            })
+            .map(|layer| layer.source.clone())
+            .collect()
+    }
+
+    /// Loads and merges the layers, logging where each syntax error is if one cannot be parsed
+    pub fn load(&self) -> Result<(Whkdrc, Provenance), WhkdError> {
+        let result = whkd_parser::load_layers(&self.sources());
+
+        if let Err(WhkdError::Parse(source, errors)) = &result {
+            for error in errors {
+                tracing::error!(
+                    source = %source,
+                    line = error.line,
+                    column = error.column,
+                    span = ?error.span,
+                    "{}",
+                    error.message
+                );
+            }
+        }
+
+        result
+    }
+}
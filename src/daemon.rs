@@ -0,0 +1,421 @@
+use crate::build_table;
+use crate::config::Layers;
+use crate::events;
+use crate::executor::ExecutorKind;
+use crate::exit;
+use crate::hotkeys::HotkeyManager;
+use crate::hotkeys::HotkeyTable;
+use crate::log_diagnostics;
+use crate::pause_toggled;
+use crate::run_hook;
+use crate::shell::ShellProgram;
+use crate::HkmData;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use parking_lot::Mutex;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use whkd_core::BindingOptions;
+use whkd_core::Chord;
+use whkd_core::Diagnostic;
+use whkd_core::Executor;
+use whkd_core::HotkeyBinding;
+use whkd_core::Resolution;
+use whkd_core::Whkdrc;
+use whkd_core::WindowContext;
+use whkd_ipc::Binding;
+use whkd_ipc::Event;
+
+/// How often the loaded whkdrc is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A change to the bindings of whkdrc made over IPC, which is kept across reloads
+#[derive(Debug, Clone)]
+struct RuntimeBinding {
+    id: u64,
+    chord: Chord,
+    /// `None` when the chord has been unregistered
+    command: Option<String>,
+    expires: Option<Instant>,
+}
+
+struct State {
+    /// The configuration as it was loaded from whkdrc
+    loaded: Arc<Whkdrc>,
+    runtime: Vec<RuntimeBinding>,
+    /// The configuration with the runtime bindings applied
+    whkdrc: Arc<Whkdrc>,
+    executor: Arc<dyn Executor>,
+}
+
+pub struct Daemon {
+    manager: HotkeyManager,
+    layers: Layers,
+    executor_kind: ExecutorKind,
+    state: Mutex<State>,
+    next_id: AtomicU64,
+}
+
+impl Daemon {
+    pub fn new(
+        manager: HotkeyManager,
+        layers: Layers,
+        whkdrc: Arc<Whkdrc>,
+        executor_kind: ExecutorKind,
+        executor: Arc<dyn Executor>,
+    ) -> Self {
+        Self {
+            manager,
+            layers,
+            executor_kind,
+            state: Mutex::new(State {
+                loaded: whkdrc.clone(),
+                runtime: vec![],
+                whkdrc,
+                executor,
+            }),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub fn whkdrc(&self) -> Arc<Whkdrc> {
+        self.state.lock().whkdrc.clone()
+    }
+
+    pub fn executor(&self) -> Arc<dyn Executor> {
+        self.state.lock().executor.clone()
+    }
+
+    /// The whkdrc identifying the configuration, see [`Layers::primary`]
+    pub fn config(&self) -> PathBuf {
+        self.layers.primary()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.manager.pause_handle().is_paused()
+    }
+
+    /// Pauses or resumes all hotkeys except the pause hotkey, running the pause hook if the
+    /// state changes
+    pub fn set_paused(&self, paused: bool) {
+        let pause_handle = self.manager.pause_handle();
+        if pause_handle.is_paused() != paused {
+            pause_handle.set(paused);
+            pause_toggled(&*self.executor(), &self.whkdrc(), paused);
+        }
+    }
+
+    /// Stops the event loop, which makes whkd exit
+    pub fn stop(&self) {
+        self.manager.interrupt();
+    }
+
+    /// Unregisters every hotkey, runs the exit hook and closes the shell session
+    pub fn shutdown(&self) {
+        self.manager.swap(HotkeyTable::new());
+        exit(&*self.executor(), &self.whkdrc());
+    }
+
+    /// Applies `runtime` on top of `loaded` and swaps in the resulting hotkeys. If the result is
+    /// invalid or any hotkey cannot be registered, the previous hotkeys and state are left in
+    /// place.
+    fn apply(
+        &self,
+        state: &mut State,
+        loaded: Arc<Whkdrc>,
+        runtime: Vec<RuntimeBinding>,
+    ) -> Result<()> {
+        let overrides = runtime
+            .iter()
+            .map(|binding| (binding.chord.clone(), binding.command.clone()))
+            .collect::<Vec<_>>();
+        let whkdrc = loaded.overlay(&overrides);
+
+        let errors = whkdrc
+            .validate()
+            .into_iter()
+            .filter(Diagnostic::is_error)
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(eyre!(errors.join("\n")));
+        }
+
+        let whkdrc = Arc::new(whkdrc);
+
+        let shell = ShellProgram::of(&whkdrc);
+        let executor = if ShellProgram::of(&state.whkdrc) == shell {
+            state.executor.clone()
+        } else {
+            self.executor_kind.executor(shell)?
+        };
+
+        let (table, failures) = build_table(&whkdrc, &executor, &self.manager.pause_handle())?;
+        if !failures.is_empty() {
+            if !Arc::ptr_eq(&executor, &state.executor) {
+                executor.shutdown();
+            }
+
+            for failure in &failures {
+                failure.publish();
+            }
+
+            let failures = failures.iter().map(ToString::to_string).collect::<Vec<_>>();
+            return Err(eyre!(failures.join("\n")));
+        }
+
+        self.manager.swap(table);
+
+        let previous = std::mem::replace(&mut state.executor, executor);
+        if !Arc::ptr_eq(&previous, &state.executor) {
+            previous.shutdown();
+        }
+
+        state.loaded = loaded;
+        state.runtime = runtime;
+        state.whkdrc = whkdrc;
+
+        Ok(())
+    }
+
+    /// Loads every layer of whkdrc again and swaps in its hotkeys, keeping any runtime bindings.
+    /// If a layer cannot be loaded or any hotkey cannot be registered, the previous hotkeys are
+    /// left in place.
+    pub fn reload(&self) -> Result<()> {
+        let (loaded, _) = self.layers.load()?;
+        let loaded = Arc::new(loaded);
+
+        log_diagnostics(&loaded);
+
+        let mut state = self.state.lock();
+        let runtime = state.runtime.clone();
+        self.apply(&mut state, loaded, runtime)?;
+
+        events::publish(&Event::ConfigReloaded {
+            config: self.layers.primary(),
+        });
+
+        Ok(())
+    }
+
+    /// Binds `chord` to `command` without changing whkdrc. When `replace` is true the chord must
+    /// already be bound, otherwise it must not be.
+    pub fn register(
+        self: &Arc<Self>,
+        chord: &str,
+        command: &str,
+        ttl: Option<Duration>,
+        replace: bool,
+    ) -> Result<()> {
+        let chord = Chord::from_str(chord)?;
+
+        // check the key names in the same way as the bindings in whkdrc
+        HkmData::try_from(&HotkeyBinding {
+            keys: chord.keys(),
+            command: command.to_string(),
+            process_name: None,
+            options: BindingOptions::default(),
+        })?;
+
+        let mut state = self.state.lock();
+
+        if state
+            .whkdrc
+            .pause_binding
+            .as_ref()
+            .is_some_and(|keys| Chord::new(keys) == chord)
+        {
+            return Err(eyre!("'{chord}' is the pause binding"));
+        }
+
+        let bound = state.whkdrc.chords().contains(&chord);
+        if replace && !bound {
+            return Err(eyre!("'{chord}' is not bound"));
+        }
+
+        if !replace && bound {
+            return Err(eyre!(
+                "'{chord}' is already bound, use replace to change its binding"
+            ));
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut runtime = state.runtime.clone();
+        runtime.retain(|binding| binding.chord != chord);
+        runtime.push(RuntimeBinding {
+            id,
+            chord,
+            command: Some(command.to_string()),
+            expires: ttl.map(|ttl| Instant::now() + ttl),
+        });
+
+        let loaded = state.loaded.clone();
+        self.apply(&mut state, loaded, runtime)?;
+
+        if let Some(ttl) = ttl {
+            let daemon = self.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(ttl);
+                daemon.expire(id);
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Removes the binding for `chord` without changing whkdrc
+    pub fn unregister(&self, chord: &str) -> Result<()> {
+        let chord = Chord::from_str(chord)?;
+
+        let mut state = self.state.lock();
+        if !state.whkdrc.chords().contains(&chord) {
+            return Err(eyre!("'{chord}' is not bound"));
+        }
+
+        let mut runtime = state.runtime.clone();
+        runtime.retain(|binding| binding.chord != chord);
+
+        if state.loaded.chords().contains(&chord) {
+            runtime.push(RuntimeBinding {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                chord,
+                command: None,
+                expires: None,
+            });
+        }
+
+        let loaded = state.loaded.clone();
+        self.apply(&mut state, loaded, runtime)
+    }
+
+    /// Removes the runtime binding with `id` if it has not been replaced or removed since
+    fn expire(&self, id: u64) {
+        let mut state = self.state.lock();
+        let Some(expired) = state.runtime.iter().find(|binding| binding.id == id) else {
+            return;
+        };
+
+        let chord = expired.chord.clone();
+        let mut runtime = state.runtime.clone();
+        runtime.retain(|binding| binding.id != id);
+
+        let loaded = state.loaded.clone();
+        match self.apply(&mut state, loaded, runtime) {
+            Ok(()) => tracing::info!(%chord, "runtime binding expired"),
+            Err(error) => {
+                tracing::error!(%chord, %error, "unable to remove the expired runtime binding");
+            }
+        }
+    }
+
+    /// Lists every bound chord, marking the ones registered at runtime
+    pub fn bindings(&self) -> Vec<Binding> {
+        let state = self.state.lock();
+        let now = Instant::now();
+
+        state
+            .whkdrc
+            .chords()
+            .into_iter()
+            .map(|chord| {
+                let runtime = state
+                    .runtime
+                    .iter()
+                    .find(|binding| binding.chord == chord && binding.command.is_some());
+
+                let command = match state.whkdrc.resolve(&chord, &WindowContext::default()) {
+                    Resolution::Run(command) => Some(command),
+                    Resolution::Ignored | Resolution::Unbound => None,
+                };
+
+                Binding {
+                    chord: chord.to_string(),
+                    command,
+                    app_specific: state.whkdrc.is_app_specific(&chord),
+                    runtime: runtime.is_some(),
+                    expires_in: runtime
+                        .and_then(|binding| binding.expires)
+                        .map(|expires| expires.saturating_duration_since(now).as_secs()),
+                }
+            })
+            .collect()
+    }
+
+    /// Runs the `.on_error` hook on another thread whenever the command of a binding fails
+    pub fn handle_failures(self: &Arc<Self>) {
+        let daemon = self.clone();
+        let failures = events::subscribe();
+
+        std::thread::spawn(move || {
+            for event in failures {
+                // failures of hooks, including this one, do not run the hook again
+                if let Event::CommandFailed {
+                    chord: Some(chord),
+                    command,
+                    exit_code,
+                    ..
+                } = event
+                {
+                    let whkdrc = daemon.whkdrc();
+                    if let Some(job) = whkdrc.on_error_job(&chord, &command, exit_code) {
+                        run_hook(&*daemon.executor(), &job);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Reloads whkdrc whenever any of its layers is created, modified or removed. These are the only
+    /// files watched, since whkdrc has no way to include others.
+    pub fn watch(self: &Arc<Self>) {
+        let daemon = self.clone();
+
+        std::thread::spawn(move || {
+            let modified = || {
+                daemon
+                    .layers
+                    .paths()
+                    .into_iter()
+                    .map(modified)
+                    .collect::<Vec<_>>()
+            };
+
+            let mut last_modified = modified();
+
+            loop {
+                std::thread::sleep(WATCH_INTERVAL);
+
+                let modified = modified();
+                if modified == last_modified {
+                    continue;
+                }
+
+                last_modified = modified;
+
+                match daemon.reload() {
+                    Ok(()) => {
+                        tracing::info!(config = %daemon.config().display(), "reloaded whkdrc");
+                    }
+                    Err(error) => tracing::error!(
+                        config = %daemon.config().display(),
+                        %error,
+                        "unable to reload whkdrc, keeping the previous bindings"
+                    ),
+                }
+            }
+        });
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
@@ -0,0 +1,21 @@
+use parking_lot::Mutex;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use whkd_ipc::Event;
+
+static SUBSCRIBERS: Mutex<Vec<Sender<Event>>> = Mutex::new(vec![]);
+
+/// Returns a receiver for every event published from now on
+pub fn subscribe() -> Receiver<Event> {
+    let (sender, receiver) = mpsc::channel();
+    SUBSCRIBERS.lock().push(sender);
+    receiver
+}
+
+/// Sends `event` to every subscriber, forgetting the subscribers which have gone away
+pub fn publish(event: &Event) {
+    SUBSCRIBERS
+        .lock()
+        .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+}
@@ -0,0 +1,992 @@
+use crate::events;
+use crate::output;
+use crate::output::Stream;
+use crate::shell::ShellProgram;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::os::windows::process::CommandExt;
+use std::process::Child;
+use std::process::ChildStderr;
+use std::process::ChildStdin;
+use std::process::ChildStdout;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use whkd_core::ExecuteError;
+use whkd_core::Executor;
+use whkd_core::Job;
+use whkd_core::Shell;
+use whkd_core::SplitError;
+use whkd_ipc::Event;
+use whkd_ipc::IpcTarget;
+
+/// How long the shell session is given to exit after its stdin has been closed
+const SESSION_EXIT_TIMEOUT: Duration = Duration::from_secs(5);
+const SESSION_EXIT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How often the shell session is checked for having exited
+const SESSION_HEALTH_INTERVAL: Duration = Duration::from_millis(500);
+/// How long a restarted shell session has to keep running for its restarts to be forgotten
+const SESSION_HEALTHY_AFTER: Duration = Duration::from_secs(60);
+/// How many times in a row the shell session is restarted before giving up
+const MAX_RESTARTS: u32 = 5;
+/// How long to wait before the second restart in a row, doubled for every restart after that
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// Printed by the shell session after each command, to tell when the session is idle again
+const IDLE_MARKER: &str = "__whkd_idle__";
+
+/// How often a spawned command is checked for having exited
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long to wait for the rest of the output of a spawned command once it has exited
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How commands are run, chosen with `--executor`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutorKind {
+    /// Write commands to a pool of persistent shell sessions of the given size
+    Session(usize),
+    /// Start a new shell process for every command
+    Spawn,
+    /// Hand commands to the whkd listening on another endpoint
+    Ipc(String),
+}
+
+impl FromStr for ExecutorKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "session" => Ok(Self::Session(1)),
+            "spawn" => Ok(Self::Spawn),
+            _ => {
+                if let Some(socket) = s.strip_prefix("ipc:").filter(|socket| !socket.is_empty()) {
+                    return Ok(Self::Ipc(socket.to_string()));
+                }
+
+                match s.strip_prefix("session:").map(str::parse) {
+                    Some(Ok(size)) if size > 0 => Ok(Self::Session(size)),
+                    _ => Err(format!(
+                        "unknown executor '{s}', expected session, session:<size>, spawn or ipc:<socket>"
+                    )),
+                }
+            }
+        }
+    }
+}
+
+impl ExecutorKind {
+    /// Creates the executor for commands run with `shell`, which starts executors for the other
+    /// shells of bindings the first time they are needed
+    pub fn executor(&self, shell: ShellProgram) -> Result<Arc<dyn Executor>> {
+        Ok(match self {
+            Self::Ipc(socket) => Arc::new(IpcTarget::new(socket)),
+            _ => Arc::new(Shells::new(self.clone(), shell)?),
+        })
+    }
+
+    fn for_shell(&self, shell: ShellProgram) -> Result<Arc<dyn Executor>> {
+        Ok(match (self, &shell.shell) {
+            (Self::Ipc(socket), _) => Arc::new(IpcTarget::new(socket)),
+            (_, Shell::None) => Arc::new(Direct {
+                echo: shell.echoes(),
+            }),
+            (Self::Session(1), _) => Arc::new(ShellSession::new(shell)?),
+            (Self::Session(size), _) => Arc::new(SessionPool::new(&shell, *size)?),
+            (Self::Spawn, _) => Arc::new(Spawn { shell }),
+        })
+    }
+}
+
+/// Runs each job with the executor for its shell, keeping one executor per shell
+///
+/// The extra arguments given to `.shell` and the commands of `.shell_init` are only used for the
+/// shell set with `.shell`, including when a binding picks the same shell.
+pub struct Shells {
+    kind: ExecutorKind,
+    shell: ShellProgram,
+    executors: Mutex<Vec<(Shell, Arc<dyn Executor>)>>,
+}
+
+impl Shells {
+    fn new(kind: ExecutorKind, shell: ShellProgram) -> Result<Self> {
+        let executor = kind.for_shell(shell.clone())?;
+
+        Ok(Self {
+            kind,
+            executors: Mutex::new(vec![(shell.shell.clone(), executor)]),
+            shell,
+        })
+    }
+
+    fn executor(&self, shell: &Shell) -> Result<Arc<dyn Executor>> {
+        let mut executors = self.executors.lock();
+
+        if let Some((_, executor)) = executors.iter().find(|(existing, _)| existing == shell) {
+            return Ok(executor.clone());
+        }
+
+        let executor = self.kind.for_shell(ShellProgram {
+            echo: self.shell.echo,
+            ..ShellProgram::new(shell.clone())
+        })?;
+        executors.push((shell.clone(), executor.clone()));
+
+        Ok(executor)
+    }
+}
+
+impl Executor for Shells {
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError> {
+        let executor = self
+            .executor(job.shell.as_ref().unwrap_or(&self.shell.shell))
+            .map_err(|error| error.to_string())?;
+
+        executor.execute(job)
+    }
+
+    fn shutdown(&self) {
+        for (_, executor) in self.executors.lock().iter() {
+            executor.shutdown();
+        }
+    }
+
+    fn state(&self) -> Option<String> {
+        let executors = self.executors.lock();
+        let states = executors
+            .iter()
+            .filter_map(|(shell, executor)| Some((shell, executor.state()?)))
+            .collect::<Vec<_>>();
+
+        match states.as_slice() {
+            [] => None,
+            [(shell, state)] if **shell == self.shell.shell => Some(state.clone()),
+            states => Some(
+                states
+                    .iter()
+                    .map(|(shell, state)| format!("{shell}: {state}"))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
+        }
+    }
+}
+
+/// The commands written to a session, used to tell which of them printed its output
+#[derive(Default)]
+struct Running {
+    /// The commands which have not finished yet, oldest first. This is always empty for shells
+    /// that whkd cannot tell to print [`IDLE_MARKER`].
+    pending: VecDeque<String>,
+    /// The last command written to a shell that cannot be told to print [`IDLE_MARKER`]
+    last: Option<String>,
+}
+
+impl Running {
+    /// The command which is most likely to be running
+    fn current(&self) -> Option<&str> {
+        self.pending
+            .front()
+            .or(self.last.as_ref())
+            .map(String::as_str)
+    }
+}
+
+struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    running: Arc<Mutex<Running>>,
+}
+
+impl Session {
+    fn spawn(shell: &ShellProgram) -> Result<Self> {
+        if shell.shell == Shell::None {
+            return Err(eyre!("there is no shell session with .shell none"));
+        }
+
+        let shell_binary = shell.program();
+
+        let mut child = Command::new(shell_binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .args(shell.session_args())
+            .spawn()?;
+
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| eyre!("could not take stdin from {shell_binary} session"))?;
+
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| eyre!("could not take stdout from {shell_binary} session"))?;
+
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| eyre!("could not take stderr from {shell_binary} session"))?;
+
+        tracing::debug!(shell = %shell, pid = child.id(), "started shell session");
+
+        let running = Arc::new(Mutex::new(Running::default()));
+        log_stdout(shell.to_string(), stdout, running.clone());
+        log_stderr(shell.to_string(), stderr, running.clone());
+
+        for line in shell.init() {
+            write!(stdin, "{line}{}", shell.terminator())?;
+        }
+
+        Ok(Self {
+            child,
+            stdin,
+            running,
+        })
+    }
+
+    /// Writes `command` followed by a line which prints [`IDLE_MARKER`] once it has finished
+    fn write(&mut self, shell: &ShellProgram, command: &str) -> std::io::Result<()> {
+        let marker = shell.print(IDLE_MARKER);
+
+        // recorded before anything is written, so that none of the output of the command is read
+        // before it is known to be running
+        {
+            let mut running = self.running.lock();
+            if marker.is_some() {
+                running.pending.push_back(command.to_string());
+            } else {
+                running.last = Some(command.to_string());
+            }
+        }
+
+        let terminator = shell.terminator();
+        write!(self.stdin, "{command}{terminator}")?;
+
+        if let Some(marker) = marker {
+            write!(self.stdin, "{marker}{terminator}")?;
+        }
+
+        Ok(())
+    }
+
+    /// Closes stdin and waits for the shell to exit, killing it if it does not exit within
+    /// [`SESSION_EXIT_TIMEOUT`]
+    fn close(self) {
+        let Self {
+            mut child, stdin, ..
+        } = self;
+
+        // closing stdin tells the shell to exit once it has run everything it has been sent
+        drop(stdin);
+
+        let deadline = Instant::now() + SESSION_EXIT_TIMEOUT;
+        loop {
+            match child.try_wait() {
+                Ok(Some(_)) => return,
+                Ok(None) if Instant::now() < deadline => {
+                    std::thread::sleep(SESSION_EXIT_INTERVAL);
+                }
+                _ => break,
+            }
+        }
+
+        tracing::warn!(
+            pid = child.id(),
+            timeout = ?SESSION_EXIT_TIMEOUT,
+            "the shell session did not exit in time, killing it"
+        );
+
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+
+    /// Kills a session which has stopped working
+    fn kill(mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Writes commands to a persistent shell session, which is watched on another thread and started
+/// again if it exits
+pub struct ShellSession {
+    supervised: Arc<Mutex<Supervised>>,
+}
+
+impl ShellSession {
+    pub fn new(shell: ShellProgram) -> Result<Self> {
+        let supervised = Arc::new(Mutex::new(Supervised {
+            session: Some(Session::spawn(&shell)?),
+            shell,
+            started: Instant::now(),
+            restarts: 0,
+            retry_at: Instant::now(),
+            stopped: false,
+        }));
+
+        let watched = Arc::downgrade(&supervised);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(SESSION_HEALTH_INTERVAL);
+
+            let Some(supervised) = watched.upgrade() else {
+                break;
+            };
+
+            let mut supervised = supervised.lock();
+            if supervised.stopped {
+                break;
+            }
+
+            supervised.check();
+        });
+
+        Ok(Self { supervised })
+    }
+
+    /// The number of commands which have not finished yet, or `None` if there is no session
+    fn pending(&self) -> Option<usize> {
+        self.supervised
+            .lock()
+            .session
+            .as_ref()
+            .map(|session| session.running.lock().pending.len())
+    }
+}
+
+impl Executor for ShellSession {
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError> {
+        let mut supervised = self.supervised.lock();
+
+        if !supervised.restart() {
+            return Err(
+                format!("The {} session is {}", supervised.shell, supervised.state()).into(),
+            );
+        }
+
+        if let Err(error) = supervised.write(&job.command) {
+            tracing::error!(
+                shell = %supervised.shell,
+                command = job.command,
+                %error,
+                "unable to write to the shell session"
+            );
+            supervised.lost();
+
+            if !supervised.restart() || supervised.write(&job.command).is_err() {
+                return Err("Unable to write to stdin session".into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn shutdown(&self) {
+        let mut supervised = self.supervised.lock();
+        supervised.stopped = true;
+
+        if let Some(session) = supervised.session.take() {
+            session.close();
+        }
+    }
+
+    fn state(&self) -> Option<String> {
+        Some(self.supervised.lock().state())
+    }
+}
+
+/// Writes commands to whichever of several persistent shell sessions has the fewest unfinished
+/// commands, or to each in turn for shells which cannot tell when a command has finished. Serial
+/// jobs always go to the first session, so they run one after another.
+pub struct SessionPool {
+    sessions: Vec<ShellSession>,
+    /// Whether the sessions can report their unfinished commands, see [`ShellProgram::print`]
+    counts_pending: bool,
+    /// The number of jobs handed out in turn so far
+    turns: AtomicUsize,
+}
+
+impl SessionPool {
+    pub fn new(shell: &ShellProgram, size: usize) -> Result<Self> {
+        Ok(Self {
+            sessions: (0..size)
+                .map(|_| ShellSession::new(shell.clone()))
+                .collect::<Result<_>>()?,
+            counts_pending: shell.print(IDLE_MARKER).is_some(),
+            turns: AtomicUsize::new(0),
+        })
+    }
+}
+
+/// The session with the fewest of `pending` commands, leaving the first session to serial jobs
+/// unless every other session is busy. Sessions which are not running are only chosen if there
+/// is nothing else.
+fn least_busy(pending: &[Option<usize>]) -> usize {
+    (1..pending.len())
+        .chain(0..1)
+        .min_by_key(|index| pending[*index].unwrap_or(usize::MAX))
+        .unwrap_or(0)
+}
+
+/// The session whose `turn` it is out of `size`, leaving the first session to serial jobs unless
+/// it is the only one
+fn in_turn(turn: usize, size: usize) -> usize {
+    match size {
+        0 | 1 => 0,
+        size => 1 + turn % (size - 1),
+    }
+}
+
+impl Executor for SessionPool {
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError> {
+        let index = if job.serial {
+            0
+        } else if self.counts_pending {
+            least_busy(
+                &self
+                    .sessions
+                    .iter()
+                    .map(ShellSession::pending)
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            // every session would report that it is idle, so they are all given the same share
+            in_turn(
+                self.turns.fetch_add(1, Ordering::Relaxed),
+                self.sessions.len(),
+            )
+        };
+
+        self.sessions[index].execute(job)
+    }
+
+    fn shutdown(&self) {
+        for session in &self.sessions {
+            session.shutdown();
+        }
+    }
+
+    fn state(&self) -> Option<String> {
+        Some(
+            self.sessions
+                .iter()
+                .enumerate()
+                .filter_map(|(index, session)| Some(format!("{}: {}", index + 1, session.state()?)))
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+}
+
+/// A shell session along with what is needed to restart it
+struct Supervised {
+    shell: ShellProgram,
+    session: Option<Session>,
+    started: Instant,
+    /// Restarts in a row, forgotten once a session has been running for [`SESSION_HEALTHY_AFTER`]
+    restarts: u32,
+    /// The earliest time the session may be restarted
+    retry_at: Instant,
+    stopped: bool,
+}
+
+impl Supervised {
+    fn write(&mut self, command: &str) -> std::io::Result<()> {
+        let session = self
+            .session
+            .as_mut()
+            .ok_or(std::io::ErrorKind::NotConnected)?;
+
+        if self.shell.echoes() {
+            output::command(&self.shell.to_string(), command);
+        }
+
+        tracing::trace!(
+            shell = %self.shell,
+            pid = session.child.id(),
+            command,
+            "writing command to the shell session"
+        );
+
+        session.write(&self.shell, command)
+    }
+
+    /// Checks whether the session is still running, restarting it if it has exited and the
+    /// backoff has passed
+    fn check(&mut self) {
+        if let Some(session) = &mut self.session {
+            match session.child.try_wait() {
+                Ok(None) => {
+                    if self.started.elapsed() >= SESSION_HEALTHY_AFTER {
+                        self.restarts = 0;
+                    }
+
+                    return;
+                }
+                Ok(Some(status)) => {
+                    tracing::warn!(shell = %self.shell, %status, "the shell session exited");
+                }
+                Err(error) => {
+                    tracing::error!(shell = %self.shell, %error, "unable to check the shell session");
+                }
+            }
+
+            self.lost();
+        }
+
+        self.restart();
+    }
+
+    /// Gets rid of the current session, killing it if it is still running, and schedules a restart
+    fn lost(&mut self) {
+        if let Some(session) = self.session.take() {
+            session.kill();
+        }
+
+        if self.restarts >= MAX_RESTARTS {
+            tracing::error!(
+                shell = %self.shell,
+                restarts = MAX_RESTARTS,
+                "the shell session has been restarted too many times in a row, giving up; restart whkd to try again"
+            );
+        }
+
+        self.retry_at = Instant::now() + backoff(self.restarts);
+    }
+
+    /// Starts a new session if there is none, the backoff has passed and the restarts have not run
+    /// out, returning whether there is a session
+    fn restart(&mut self) -> bool {
+        if self.session.is_some() {
+            return true;
+        }
+
+        if self.stopped || self.restarts >= MAX_RESTARTS || Instant::now() < self.retry_at {
+            return false;
+        }
+
+        self.restarts += 1;
+
+        match Session::spawn(&self.shell) {
+            Ok(session) => {
+                tracing::info!(
+                    shell = %self.shell,
+                    pid = session.child.id(),
+                    attempt = self.restarts,
+                    max_attempts = MAX_RESTARTS,
+                    "restarted the shell session"
+                );
+
+                self.session = Some(session);
+                self.started = Instant::now();
+                events::publish(&Event::ShellRespawned {
+                    shell: self.shell.to_string(),
+                });
+
+                true
+            }
+            Err(error) => {
+                tracing::error!(
+                    shell = %self.shell,
+                    %error,
+                    attempt = self.restarts,
+                    max_attempts = MAX_RESTARTS,
+                    "unable to restart the shell session"
+                );
+
+                self.retry_at = Instant::now() + backoff(self.restarts);
+
+                false
+            }
+        }
+    }
+
+    fn state(&self) -> String {
+        match &self.session {
+            _ if self.stopped => String::from("stopped"),
+            Some(session) if self.restarts == 0 => format!("running (pid {})", session.child.id()),
+            Some(session) => format!(
+                "running (pid {}, restarted {} of {MAX_RESTARTS} times)",
+                session.child.id(),
+                self.restarts
+            ),
+            None if self.restarts >= MAX_RESTARTS => {
+                format!("failed (restarted {MAX_RESTARTS} times in a row)")
+            }
+            None => format!(
+                "restarting (attempt {} of {MAX_RESTARTS})",
+                self.restarts + 1
+            ),
+        }
+    }
+}
+
+/// How long to wait before restarting the session after `restarts` restarts in a row
+fn backoff(restarts: u32) -> Duration {
+    match restarts {
+        0 => Duration::ZERO,
+        restarts => RESTART_BACKOFF
+            .saturating_mul(2_u32.saturating_pow(restarts - 1))
+            .min(RESTART_BACKOFF_MAX),
+    }
+}
+
+/// Writes each line printed by a session to the output log on another thread, counting the
+/// commands which have finished
+fn log_stdout(shell: String, stdout: ChildStdout, running: Arc<Mutex<Running>>) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            let mut running = running.lock();
+            if line.trim() == IDLE_MARKER {
+                running.pending.pop_front();
+            } else {
+                output::line(&shell, Stream::Stdout, running.current(), &line);
+            }
+        }
+    });
+}
+
+/// Writes each line a session prints to stderr to the output log on another thread
+fn log_stderr(shell: String, stderr: ChildStderr, running: Arc<Mutex<Running>>) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            output::line(&shell, Stream::Stderr, running.lock().current(), &line);
+        }
+    });
+}
+
+/// Starts a new shell process for every command, waiting for it in the background
+pub struct Spawn {
+    shell: ShellProgram,
+}
+
+impl Executor for Spawn {
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError> {
+        if self.shell.shell == Shell::None {
+            return Direct {
+                echo: self.shell.echoes(),
+            }
+            .execute(job);
+        }
+
+        let mut command = Command::new(self.shell.program());
+        command
+            .args(self.shell.command_args(&job.command))
+            .envs(job.env.iter().cloned());
+
+        if let Some(command_line) = self.shell.raw_command_line(&job.command) {
+            command.raw_arg(command_line);
+        }
+
+        supervise(command, job, &self.shell.to_string(), self.shell.echoes())
+    }
+}
+
+/// Runs commands as programs with arguments, without a shell, waiting for them in the background
+///
+/// The program is looked up in `PATH`, trying the extensions in `PATHEXT`, and the arguments are
+/// quoted for the program by [`Command`].
+pub struct Direct {
+    echo: bool,
+}
+
+impl Executor for Direct {
+    fn execute(&self, job: &Job) -> Result<(), ExecuteError> {
+        let arguments = whkd_core::split_command(&job.command)?;
+        let (program, arguments) = arguments.split_first().ok_or(SplitError::Empty)?;
+
+        let program = whkd_core::find_program(
+            program,
+            std::env::var_os("PATH").as_deref(),
+            std::env::var_os("PATHEXT").as_deref(),
+        )
+        .ok_or_else(|| format!("could not find '{program}' in PATH"))?;
+
+        let mut command = Command::new(program);
+        command.args(arguments).envs(job.env.iter().cloned());
+
+        supervise(command, job, &Shell::None.to_string(), self.echo)
+    }
+}
+
+/// How a process started by [`supervise`] finished
+enum Outcome {
+    Exited(ExitStatus),
+    TimedOut(Duration),
+    Failed(std::io::Error),
+}
+
+/// Starts `command` with its output captured, and waits for it on another thread. The process
+/// and everything it started are killed if it is still running after the timeout of `job`. Its
+/// output is written to the output log under `shell` once it has finished, and failures are
+/// logged.
+///
+/// Serial jobs are queued on [`SERIAL`] instead, and are only started once the serial jobs before
+/// them have finished.
+fn supervise(command: Command, job: &Job, shell: &str, echo: bool) -> Result<(), ExecuteError> {
+    let mut supervised = Supervise {
+        command,
+        job: job.clone(),
+        shell: shell.to_string(),
+        echo,
+    };
+
+    if job.serial {
+        return queue_serial(supervised);
+    }
+
+    let started = supervised.start()?;
+    std::thread::spawn(move || supervised.finish(started));
+
+    Ok(())
+}
+
+/// Serial jobs started by [`Spawn`] and [`Direct`], which are run one at a time on their own thread
+static SERIAL: Mutex<Option<Sender<Supervise>>> = Mutex::new(None);
+
+fn queue_serial(supervised: Supervise) -> Result<(), ExecuteError> {
+    let mut queue = SERIAL.lock();
+    let sender = queue.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel::<Supervise>();
+        std::thread::spawn(move || {
+            for mut supervised in receiver {
+                // there is nobody left to return an error to, so it is reported like a failure
+                match supervised.start() {
+                    Ok(started) => supervised.finish(started),
+                    Err(error) => {
+                        let message = format!(
+                            "Unable to start '{}' (error: {error})",
+                            supervised.job.command
+                        );
+                        failed(&supervised.job, None, None, message);
+                    }
+                }
+            }
+        });
+
+        sender
+    });
+
+    sender
+        .send(supervised)
+        .map_err(|_| "the serial job thread has stopped".into())
+}
+
+/// A command started for a job by [`supervise`]
+struct Supervise {
+    command: Command,
+    job: Job,
+    shell: String,
+    echo: bool,
+}
+
+/// A process started by [`Supervise::start`]
+struct Started {
+    child: Child,
+    at: Instant,
+    stdout: Receiver<String>,
+    stderr: Receiver<String>,
+}
+
+impl Supervise {
+    fn start(&mut self) -> std::io::Result<Started> {
+        if self.echo {
+            output::command(&self.shell, &self.job.command);
+        }
+
+        let mut child = self
+            .command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        tracing::debug!(
+            shell = self.shell,
+            pid = child.id(),
+            command = self.job.command,
+            "started command"
+        );
+
+        Ok(Started {
+            at: Instant::now(),
+            stdout: capture(child.stdout.take()),
+            stderr: capture(child.stderr.take()),
+            child,
+        })
+    }
+
+    /// Waits for the process to finish, then logs its output and whether it failed
+    fn finish(&self, mut started: Started) {
+        let Self { job, shell, .. } = self;
+        let outcome = wait(&mut started.child, job.timeout);
+
+        // a process started in the background by the command can keep the pipes open after the
+        // command itself has exited, so only wait a moment for the rest of the output
+        let stdout = started
+            .stdout
+            .recv_timeout(OUTPUT_TIMEOUT)
+            .unwrap_or_default();
+        let stderr = started
+            .stderr
+            .recv_timeout(OUTPUT_TIMEOUT)
+            .unwrap_or_default();
+
+        for (stream, output) in [(Stream::Stdout, stdout), (Stream::Stderr, stderr)] {
+            for line in output.lines() {
+                output::line(shell, stream, Some(&job.command), line);
+            }
+        }
+
+        let (exit_code, message) = match outcome {
+            Outcome::Exited(status) if status.success() => {
+                tracing::debug!(command = job.command, runtime = ?started.at.elapsed(), "command finished");
+                return;
+            }
+            Outcome::Exited(status) => (
+                status.code(),
+                format!("'{}' failed ({status})", job.command),
+            ),
+            Outcome::TimedOut(timeout) => (
+                None,
+                format!(
+                    "'{}' did not finish within {}, it has been killed",
+                    job.command,
+                    format_duration(timeout)
+                ),
+            ),
+            Outcome::Failed(error) => (
+                None,
+                format!("Unable to wait for '{}' (error: {error})", job.command),
+            ),
+        };
+
+        failed(job, exit_code, Some(started.at.elapsed()), message);
+    }
+}
+
+/// Logs and publishes the failure of a command run in the background
+fn failed(job: &Job, exit_code: Option<i32>, runtime: Option<Duration>, message: String) {
+    tracing::error!(
+        chord = job.chord.as_ref().map(ToString::to_string),
+        command = job.command,
+        exit_code,
+        ?runtime,
+        "{message}"
+    );
+    events::publish(&Event::CommandFailed {
+        chord: job.chord.as_ref().map(ToString::to_string),
+        command: job.command.clone(),
+        exit_code,
+        message,
+    });
+}
+
+/// Reads everything from `pipe` on another thread, sending it once the pipe is closed
+fn capture(pipe: Option<impl Read + Send + 'static>) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+
+    if let Some(mut pipe) = pipe {
+        std::thread::spawn(move || {
+            let mut output = vec![];
+            let _ = pipe.read_to_end(&mut output);
+            let _ = sender.send(String::from_utf8_lossy(&output).into_owned());
+        });
+    }
+
+    receiver
+}
+
+/// Waits for `child` to exit, killing its process tree once `timeout` has passed
+fn wait(child: &mut Child, timeout: Option<Duration>) -> Outcome {
+    let Some(timeout) = timeout else {
+        return child.wait().map_or_else(Outcome::Failed, Outcome::Exited);
+    };
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Outcome::Exited(status),
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(PROCESS_POLL_INTERVAL),
+            Ok(None) => break,
+            Err(error) => return Outcome::Failed(error),
+        }
+    }
+
+    kill_tree(child);
+
+    Outcome::TimedOut(timeout)
+}
+
+/// Kills `child` along with every process it has started
+fn kill_tree(child: &mut Child) {
+    let killed = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &child.id().to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success());
+
+    if !killed {
+        let _ = child.kill();
+    }
+
+    let _ = child.wait();
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.subsec_millis() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_least_busy() {
+        assert_eq!(least_busy(&[Some(3), Some(2), Some(1)]), 2);
+        assert_eq!(least_busy(&[Some(0), Some(0), Some(0)]), 1);
+        assert_eq!(least_busy(&[Some(0), Some(1), Some(1)]), 0);
+        assert_eq!(least_busy(&[Some(3), None, Some(4)]), 0);
+        assert_eq!(least_busy(&[None]), 0);
+    }
+
+    #[test]
+    fn test_in_turn() {
+        // the pending commands of a Custom shell are unknown, so the sessions take turns rather
+        // than the second session getting every job
+        let sessions = (0..6).map(|turn| in_turn(turn, 3)).collect::<Vec<_>>();
+        assert_eq!(sessions, vec![1, 2, 1, 2, 1, 2]);
+
+        assert_eq!(in_turn(0, 2), 1);
+        assert_eq!(in_turn(5, 2), 1);
+        assert_eq!(in_turn(7, 1), 0);
+    }
+}
@@ -0,0 +1,199 @@
+//! whkd drives the keyboard hook from `win_hotkeys` itself instead of using
+//! `win_hotkeys::HotkeyManager`, whose event loop borrows the manager mutably for as long as it
+//! runs and cannot safely be restarted. Keeping the registered hotkeys in a shared table means
+//! they can be swapped atomically while the event loop is running.
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use win_hotkeys::error::WHKError;
+use win_hotkeys::hook;
+use win_hotkeys::hook::KeyAction;
+use win_hotkeys::hook::KeyboardEvent;
+use win_hotkeys::hotkey::Hotkey;
+use win_hotkeys::state::KeyboardState;
+use win_hotkeys::VKey;
+
+type Callback = Arc<dyn Fn() + Send + Sync>;
+
+struct Entry {
+    hotkey: Hotkey<()>,
+    callback: Callback,
+    pause: bool,
+}
+
+/// A set of hotkeys which can be installed in a [`HotkeyManager`]
+#[derive(Default)]
+pub struct HotkeyTable {
+    hotkeys: HashMap<u16, Vec<Entry>>,
+}
+
+impl HotkeyTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn insert(
+        &mut self,
+        trigger_key: VKey,
+        mod_keys: &[VKey],
+        callback: Callback,
+        pause: bool,
+    ) -> Result<i32, WHKError> {
+        let hotkey = Hotkey::new(trigger_key, mod_keys, || {});
+        let id = hotkey.generate_id();
+
+        if self
+            .hotkeys
+            .values()
+            .any(|entries| entries.iter().any(|e| e.hotkey.generate_id() == id))
+        {
+            return Err(WHKError::RegistrationFailed);
+        }
+
+        self.hotkeys
+            .entry(trigger_key.to_vk_code())
+            .or_default()
+            .push(Entry {
+                hotkey,
+                callback,
+                pause,
+            });
+
+        Ok(id)
+    }
+
+    pub fn register_hotkey(
+        &mut self,
+        trigger_key: VKey,
+        mod_keys: &[VKey],
+        callback: impl Fn() + Send + Sync + 'static,
+    ) -> Result<i32, WHKError> {
+        self.insert(trigger_key, mod_keys, Arc::new(callback), false)
+    }
+
+    /// Registers a hotkey which toggles the paused state of the [`HotkeyManager`] before running
+    /// `callback`. Pause hotkeys are the only hotkeys that trigger while paused.
+    pub fn register_pause_hotkey(
+        &mut self,
+        trigger_key: VKey,
+        mod_keys: &[VKey],
+        callback: impl Fn() + Send + Sync + 'static,
+    ) -> Result<i32, WHKError> {
+        self.insert(trigger_key, mod_keys, Arc::new(callback), true)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PauseHandle {
+    paused: Arc<AtomicBool>,
+}
+
+impl PauseHandle {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn toggle(&self) -> bool {
+        !self.paused.fetch_xor(true, Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
+pub struct HotkeyManager {
+    table: Arc<Mutex<HotkeyTable>>,
+    paused: PauseHandle,
+    interrupt: Arc<AtomicBool>,
+}
+
+impl HotkeyManager {
+    pub fn new(table: HotkeyTable) -> Self {
+        Self {
+            table: Arc::new(Mutex::new(table)),
+            paused: PauseHandle {
+                paused: Arc::new(AtomicBool::new(false)),
+            },
+            interrupt: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Replaces every registered hotkey at once, returning the previous table
+    pub fn swap(&self, table: HotkeyTable) -> HotkeyTable {
+        std::mem::replace(&mut *self.table.lock(), table)
+    }
+
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.paused.clone()
+    }
+
+    /// Listens for keyboard events and runs the callbacks of matching hotkeys until
+    /// [`HotkeyManager::interrupt`] is called
+    pub fn event_loop(&self) {
+        let hook = hook::start();
+
+        while !self.interrupt.load(Ordering::Relaxed) {
+            let Ok(event) = hook.recv() else {
+                break;
+            };
+
+            let KeyboardEvent::KeyDown {
+                vk_code,
+                keyboard_state,
+            } = event
+            else {
+                continue;
+            };
+
+            let triggered = {
+                let table = self.table.lock();
+                let paused = self.paused.is_paused();
+
+                table.hotkeys.get(&vk_code).and_then(|entries| {
+                    entries
+                        .iter()
+                        .filter(|e| !paused || e.pause)
+                        .find(|e| e.hotkey.is_trigger_state(keyboard_state))
+                        .map(|e| (e.callback.clone(), e.pause))
+                })
+            };
+
+            match triggered {
+                Some((callback, pause)) => {
+                    if keyboard_state.is_down(VKey::LWin.to_vk_code()) {
+                        hook.key_action(KeyAction::Replace);
+                    } else {
+                        hook.key_action(KeyAction::Block);
+                    }
+
+                    if pause {
+                        self.paused.toggle();
+                    }
+
+                    callback();
+                }
+                None => hook.key_action(KeyAction::Allow),
+            }
+        }
+
+        hook.exit();
+    }
+
+    /// Stops the event loop
+    pub fn interrupt(&self) {
+        self.interrupt.store(true, Ordering::Relaxed);
+
+        // wake the event loop up so that it sees the interrupt
+        if let Some(sender) = &*hook::HOOK_EVENT_TX.read().unwrap() {
+            let _ = sender.send(KeyboardEvent::KeyDown {
+                vk_code: 0,
+                keyboard_state: KeyboardState::new(),
+            });
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::TryLockError;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use whkd_ipc::Request;
+
+/// How long to wait for a running whkd to exit when replacing it
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLACE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Held for as long as whkd runs with a given whkdrc, and released by the operating system when
+/// the process exits
+pub struct InstanceLock {
+    _file: File,
+}
+
+fn lock_path(config: &Path) -> PathBuf {
+    std::env::temp_dir().join(format!("{}.lock", whkd_ipc::instance_key(config)))
+}
+
+fn try_lock(file: &File) -> Result<bool> {
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(error)) => Err(error.into()),
+    }
+}
+
+/// Makes sure that only one whkd runs with `config`. If another instance holds the lock and
+/// `replace` is true, it is asked to stop and the lock is taken over once it has exited.
+pub fn lock(config: &Path, replace: bool) -> Result<InstanceLock> {
+    let path = lock_path(config);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|error| {
+            eyre!(
+                "could not open the instance lock at {} (error: {error})",
+                path.display()
+            )
+        })?;
+
+    if try_lock(&file)? {
+        return Ok(InstanceLock { _file: file });
+    }
+
+    if !replace {
+        return Err(eyre!(
+            "whkd is already running with {}, stop it with `whkdc stop` or start whkd with --replace to take over",
+            config.display()
+        ));
+    }
+
+    whkd_ipc::send(&whkd_ipc::socket_name(config), &Request::Stop)
+        .map_err(|error| eyre!("could not ask the running whkd to stop (error: {error})"))?;
+
+    let deadline = Instant::now() + REPLACE_TIMEOUT;
+    while Instant::now() < deadline {
+        std::thread::sleep(REPLACE_INTERVAL);
+
+        if try_lock(&file)? {
+            return Ok(InstanceLock { _file: file });
+        }
+    }
+
+    Err(eyre!(
+        "the running whkd did not exit within {} seconds",
+        REPLACE_TIMEOUT.as_secs()
+    ))
+}
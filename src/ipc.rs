@@ -0,0 +1,251 @@
+use crate::daemon::Daemon;
+use crate::dispatch;
+use crate::events;
+use crate::window_context;
+use crate::worker;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::io::BufReader;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::time::Duration;
+use whkd_core::Chord;
+use whkd_core::Job;
+use whkd_core::Resolution;
+use whkd_ipc::Listener;
+use whkd_ipc::Outcome;
+use whkd_ipc::Request;
+use whkd_ipc::Response;
+use whkd_ipc::Status;
+use whkd_ipc::Stream;
+
+/// How often a subscribed connection is checked for the client having gone away
+const SUBSCRIBER_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Serves requests from `whkdc` and other clients on a background thread
+pub fn serve(daemon: Arc<Daemon>, listener: Listener) {
+    std::thread::spawn(move || loop {
+        match whkd_ipc::accept(&listener) {
+            Ok(stream) => {
+                let daemon = daemon.clone();
+                std::thread::spawn(move || handle(&daemon, stream));
+            }
+            Err(error) => tracing::warn!(%error, "unable to accept IPC connection"),
+        }
+    });
+}
+
+fn handle(daemon: &Arc<Daemon>, stream: Stream) {
+    let mut stream = BufReader::new(stream);
+
+    let request = match whkd_ipc::read_message::<Request>(&mut stream) {
+        Ok(request) => request,
+        Err(error) => {
+            let _ = whkd_ipc::write_message(
+                stream.get_mut(),
+                &Response::Error {
+                    message: error.to_string(),
+                },
+            );
+            return;
+        }
+    };
+
+    let response = match &request {
+        Request::Pause => {
+            daemon.set_paused(true);
+            Response::Ok
+        }
+        Request::Resume => {
+            daemon.set_paused(false);
+            Response::Ok
+        }
+        Request::TogglePause => {
+            daemon.set_paused(!daemon.is_paused());
+            Response::Ok
+        }
+        Request::Reload => respond(daemon.reload()),
+        Request::Status => {
+            let whkdrc = daemon.whkdrc();
+
+            Response::Status(Status {
+                pid: std::process::id(),
+                paused: daemon.is_paused(),
+                config: daemon.config(),
+                shell: whkdrc.shell.to_string(),
+                bindings: whkdrc.chords().len(),
+                session: daemon.executor().state(),
+            })
+        }
+        Request::Stop => Response::Ok,
+        Request::Register {
+            chord,
+            command,
+            ttl,
+        } => respond(daemon.register(chord, command, ttl.map(Duration::from_secs), false)),
+        Request::Replace {
+            chord,
+            command,
+            ttl,
+        } => respond(daemon.register(chord, command, ttl.map(Duration::from_secs), true)),
+        Request::Unregister { chord } => respond(daemon.unregister(chord)),
+        Request::ListBindings => Response::Bindings {
+            bindings: daemon.bindings(),
+        },
+        Request::Run {
+            command,
+            env,
+            timeout_ms,
+            serial,
+            chord,
+        } => respond(run(
+            daemon,
+            command,
+            env,
+            *timeout_ms,
+            *serial,
+            chord.as_deref(),
+        )),
+        Request::Trigger { chord, app } => trigger(daemon, chord, app.as_deref()),
+        Request::Subscribe => return stream_events(stream.into_inner()),
+    };
+
+    if let Err(error) = whkd_ipc::write_message(stream.get_mut(), &response) {
+        tracing::warn!(?request, %error, "unable to respond to IPC request");
+    }
+
+    if request == Request::Stop {
+        daemon.stop();
+    }
+}
+
+fn respond(result: Result<()>) -> Response {
+    match result {
+        Ok(()) => Response::Ok,
+        Err(error) => Response::Error {
+            message: error.to_string(),
+        },
+    }
+}
+
+/// Runs a command sent by another whkd, which has already expanded it, so it is run as it is
+fn run(
+    daemon: &Daemon,
+    command: &str,
+    env: &[(String, String)],
+    timeout_ms: Option<u64>,
+    serial: bool,
+    chord: Option<&str>,
+) -> Result<()> {
+    let job = daemon.whkdrc().job(command);
+    let job = Job {
+        env: env.to_vec(),
+        timeout: timeout_ms.map(Duration::from_millis).or(job.timeout),
+        serial,
+        chord: chord.map(Chord::from_str).transpose()?,
+        ..job
+    };
+
+    daemon
+        .executor()
+        .execute(&job)
+        .map_err(|error| eyre!(error))
+}
+
+/// Sends every event to the client until it disconnects
+fn stream_events(stream: Stream) {
+    let events = events::subscribe();
+    let (mut receiving, mut sending) = whkd_ipc::split(stream);
+
+    if whkd_ipc::write_message(&mut sending, &Response::Ok).is_err() {
+        return;
+    }
+
+    // the client sends nothing after subscribing, so reading only finishes once it has gone away,
+    // which would otherwise not be noticed until the next event fails to be written
+    let closed = Arc::new(AtomicBool::new(false));
+    std::thread::spawn({
+        let closed = closed.clone();
+        move || {
+            let _ = std::io::copy(&mut receiving, &mut std::io::sink());
+            closed.store(true, Ordering::Relaxed);
+        }
+    });
+
+    loop {
+        match events.recv_timeout(SUBSCRIBER_CHECK_INTERVAL) {
+            Ok(event) => {
+                if whkd_ipc::write_message(&mut sending, &event).is_err() {
+                    return;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if !closed.load(Ordering::Relaxed) => {}
+            // the subscription is forgotten the next time an event is published
+            Err(_) => return,
+        }
+    }
+}
+
+/// Handles a chord in the same way as the hotkey callbacks registered for it
+fn trigger(daemon: &Daemon, chord: &str, app: Option<&str>) -> Response {
+    let chord = match Chord::from_str(chord) {
+        Ok(chord) => chord,
+        Err(error) => {
+            return Response::Error {
+                message: error.to_string(),
+            }
+        }
+    };
+
+    let whkdrc = daemon.whkdrc();
+
+    if whkdrc
+        .pause_binding
+        .as_ref()
+        .is_some_and(|keys| Chord::new(keys) == chord)
+    {
+        let paused = !daemon.is_paused();
+        daemon.set_paused(paused);
+
+        return Response::Triggered {
+            command: whkdrc.pause_hook.clone(),
+            outcome: Outcome::PauseToggled { paused },
+        };
+    }
+
+    if daemon.is_paused() {
+        return Response::Triggered {
+            command: None,
+            outcome: Outcome::Paused,
+        };
+    }
+
+    // as with the hotkeys, the command is run on the worker so that it is queued behind them
+    let context = window_context(&whkdrc, &chord, app);
+    let executor = daemon.executor();
+    let (sender, receiver) = mpsc::channel();
+
+    worker::queue(move || {
+        let (resolution, result) = dispatch(&*executor, &whkdrc, &chord, &context);
+        let _ = sender.send((resolution, result.map_err(|error| error.to_string())));
+    });
+
+    let Ok((resolution, result)) = receiver.recv() else {
+        return Response::Error {
+            message: String::from("the worker thread has stopped, unable to run the hotkey"),
+        };
+    };
+
+    let (command, outcome) = match (resolution, result) {
+        (Resolution::Run(command), Ok(())) => (Some(command), Outcome::Sent),
+        (Resolution::Run(command), Err(message)) => (Some(command), Outcome::Failed { message }),
+        (Resolution::Ignored, _) => (None, Outcome::Ignored),
+        (Resolution::Unbound, _) => (None, Outcome::Unbound),
+    };
+
+    Response::Triggered { command, outcome }
+}
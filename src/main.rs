@@ -1,115 +1,188 @@
 #![warn(clippy::all)]
 #![allow(clippy::missing_errors_doc, clippy::redundant_pub_crate)]
 
+use clap::ArgAction;
+use clap::Args;
 use clap::Parser;
-use color_eyre::eyre::eyre;
+use clap::Subcommand;
 use color_eyre::eyre::Result;
-use lazy_static::lazy_static;
-use parking_lot::Mutex;
-use std::collections::HashMap;
-use std::io::Write;
+use std::fmt::Display;
+use std::fmt::Formatter;
 use std::path::PathBuf;
-use std::process::ChildStdin;
-use std::process::Command;
-use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::level_filters::LevelFilter;
+use whkd_core::Chord;
+use whkd_core::ExecuteError;
+use whkd_core::Executor;
 use whkd_core::HotkeyBinding;
-use whkd_core::Shell;
+use whkd_core::Job;
+use whkd_core::Provenance;
+use whkd_core::Resolution;
 use whkd_core::Whkdrc;
+use whkd_core::WindowContext;
+use whkd_ipc::Event;
 use win_hotkeys::error::WHKError;
-use win_hotkeys::HotkeyManager;
 use win_hotkeys::VKey;
 
-lazy_static! {
-    static ref WHKDRC: Whkdrc = {
-        // config file defaults to `~/.config/whkdrc`, or `<WHKD_CONFIG_HOME>/whkdrc`
-        let mut home  = std::env::var("WHKD_CONFIG_HOME").map_or_else(
-            |_| dirs::home_dir().expect("no home directory found").join(".config"),
-            |home_path| {
-                let home = PathBuf::from(&home_path);
-
-                if home.as_path().is_dir() {
-                    home
-                } else {
-                    panic!(
-                        "$Env:WHKD_CONFIG_HOME is set to '{home_path}', which is not a valid directory",
-                    );
-                }
-            },
-        );
-        home.push("whkdrc");
-        whkd_parser::load(&home).unwrap_or_else(|_| panic!("could not load whkdrc from {home:?}"))
-    };
-    static ref SESSION_STDIN: Mutex<Option<ChildStdin>> = Mutex::new(None);
-}
+mod config;
+mod daemon;
+mod events;
+mod executor;
+mod hotkeys;
+mod instance;
+mod ipc;
+mod output;
+mod shell;
+mod worker;
+
+use config::Layers;
+use daemon::Daemon;
+use executor::ExecutorKind;
+use hotkeys::HotkeyManager;
+use hotkeys::HotkeyTable;
+use hotkeys::PauseHandle;
+use shell::ShellProgram;
 
 #[derive(Debug, Clone)]
 pub struct HkmData {
     pub mod_keys: Vec<VKey>,
     pub vkey: VKey,
+    pub chord: Chord,
     pub command: String,
     pub process_name: Option<String>,
 }
 
 impl HkmData {
-    pub fn register(&self, hkm: &mut HotkeyManager<()>, shell: Shell) -> Result<()> {
-        let cmd = self.command.clone();
-
-        if let Err(error) = hkm.register_hotkey(self.vkey, self.mod_keys.as_slice(), move || {
-            let mut retry_with_new_session = false;
+    pub fn register(
+        &self,
+        table: &mut HotkeyTable,
+        whkdrc: &Arc<Whkdrc>,
+        executor: &Arc<dyn Executor>,
+    ) -> Result<(), WHKError> {
+        let whkdrc = whkdrc.clone();
+        let executor = executor.clone();
+        let chord = self.chord.clone();
+
+        table.register_hotkey(self.vkey, self.mod_keys.as_slice(), move || {
+            // the active window is looked up straight away, and the rest is left to the worker
+            let context = window_context(&whkdrc, &chord, None);
+            let (executor, whkdrc, chord) = (executor.clone(), whkdrc.clone(), chord.clone());
+
+            worker::queue(move || {
+                // the outcome is logged by dispatch
+                let _ = dispatch(&*executor, &whkdrc, &chord, &context);
+            });
+        })?;
 
-            if let Some(session_stdin) = SESSION_STDIN.lock().as_mut() {
-                if matches!(shell, Shell::Pwsh | Shell::Powershell) {
-                    println!("{cmd}");
-                }
+        Ok(())
+    }
+}
 
-                if writeln!(session_stdin, "{cmd}").is_err() {
-                    retry_with_new_session = true;
-                }
-            }
+/// The context `chord` is resolved in, which is the active window for app-specific bindings
+/// unless `app` is given
+pub fn window_context(whkdrc: &Whkdrc, chord: &Chord, app: Option<&str>) -> WindowContext {
+    match app {
+        Some(app) => WindowContext::for_app(app),
+        None if whkdrc.is_app_specific(chord) => WindowContext::for_app(
+            &active_win_pos_rs::get_active_window()
+                .unwrap_or_default()
+                .app_name,
+        ),
+        None => WindowContext::default(),
+    }
+}
 
-            if retry_with_new_session && spawn_shell(shell).is_ok() {
-                if let Some(session_stdin) = SESSION_STDIN.lock().as_mut() {
-                    if matches!(shell, Shell::Pwsh | Shell::Powershell) {
-                        println!("{cmd}");
-                    }
+/// Resolves `chord` in `context` and runs the resulting command with `executor`, logging the
+/// outcome along with how long it took to hand the command to `executor`
+pub fn dispatch(
+    executor: &dyn Executor,
+    whkdrc: &Whkdrc,
+    chord: &Chord,
+    context: &WindowContext,
+) -> (Resolution, Result<(), ExecuteError>) {
+    let started = Instant::now();
+
+    let (resolution, result) = whkdrc.run(executor, chord, context);
+    let process = context.app_name.as_deref().unwrap_or_default();
+    let latency = started.elapsed();
+
+    if let Resolution::Run(command) = &resolution {
+        events::publish(&Event::HotkeyFired {
+            chord: chord.to_string(),
+            app: context.app_name.clone(),
+            command: command.clone(),
+        });
+    }
 
-                    if writeln!(session_stdin, "{cmd}").is_err() {
-                        eprintln!("Unable to write to stdin session");
-                    }
-                }
-            }
-        }) {
-            eprintln!(
-                "Unable to bind '{:?} + {}' to '{}' (error: {error}), ignoring this binding and continuing...",
-                self.mod_keys, self.vkey, self.command
-            );
+    match (&resolution, &result) {
+        (Resolution::Run(command), Ok(())) => {
+            tracing::info!(%chord, process, command, ?latency, "dispatched");
+        }
+        (Resolution::Run(command), Err(error)) => {
+            tracing::error!(%chord, process, command, ?latency, %error, "unable to run command");
+
+            events::publish(&Event::CommandFailed {
+                chord: Some(chord.to_string()),
+                command: command.clone(),
+                exit_code: None,
+                message: error.to_string(),
+            });
         }
+        (Resolution::Ignored, _) => tracing::debug!(%chord, process, "ignored"),
+        (Resolution::Unbound, _) => tracing::debug!(%chord, process, "not bound"),
+    }
 
-        Ok(())
+    (resolution, result)
+}
+
+/// Runs the job of a hook with `executor`, reporting any error
+pub fn run_hook(executor: &dyn Executor, job: &Job) {
+    if let Err(error) = whkd_core::execute(executor, job) {
+        tracing::error!(command = job.command, %error, "unable to run hook");
+    }
+}
+
+/// Logs each diagnostic of `whkdrc` at the level of its severity
+pub fn log_diagnostics(whkdrc: &Whkdrc) {
+    for diagnostic in whkdrc.validate() {
+        if diagnostic.is_error() {
+            tracing::error!("{diagnostic}");
+        } else {
+            tracing::warn!("{diagnostic}");
+        }
     }
 }
 
+fn parse_keys(keys: &[String]) -> Result<(Vec<VKey>, VKey), WHKError> {
+    let mut mod_keys = vec![];
+
+    let (mod_keys, vkey) = if keys.len() == 1 {
+        (vec![], VKey::from_keyname(&keys[0])?)
+    } else {
+        let (trigger, mods) = keys.split_last().unwrap();
+        let vkey = VKey::from_keyname(trigger)?;
+        for m in mods {
+            mod_keys.push(VKey::from_keyname(m)?);
+        }
+
+        (mod_keys, vkey)
+    };
+
+    Ok((mod_keys, vkey))
+}
+
 impl TryFrom<&HotkeyBinding> for HkmData {
     type Error = WHKError;
 
     fn try_from(value: &HotkeyBinding) -> Result<Self, Self::Error> {
-        let mut mod_keys = vec![];
-
-        let (mod_keys, vkey) = if value.keys.len() == 1 {
-            (vec![], VKey::from_keyname(&value.keys[0])?)
-        } else {
-            let (trigger, mods) = value.keys.split_last().unwrap();
-            let vkey = VKey::from_keyname(trigger)?;
-            for m in mods {
-                mod_keys.push(VKey::from_keyname(m)?);
-            }
-
-            (mod_keys, vkey)
-        };
+        let (mod_keys, vkey) = parse_keys(&value.keys)?;
 
         Ok(Self {
             mod_keys,
             vkey,
+            chord: Chord::new(&value.keys),
             command: value.command.clone(),
             process_name: value.process_name.clone(),
         })
@@ -119,225 +192,388 @@ impl TryFrom<&HotkeyBinding> for HkmData {
 #[derive(Parser)]
 #[clap(author, about, version)]
 struct Cli {
-    /// Path to whkdrc
-    #[clap(action, short, long)]
-    config: Option<PathBuf>,
+    /// Path to whkdrc, or - to read it from stdin. Can be given more than once to merge several
+    /// files in order.
+    #[clap(short, long, global = true)]
+    config: Vec<PathBuf>,
+    /// Stop the whkd which is already running with the same whkdrc and take over from it
+    #[clap(long)]
+    replace: bool,
+    /// How commands are run: session (a persistent shell session), session:<size> (a pool of
+    /// persistent shell sessions), spawn (a new shell process for every command) or ipc:<socket>
+    /// (handed to the whkd listening on <socket>)
+    #[clap(long, default_value = "session")]
+    executor: ExecutorKind,
+    /// Where the output of commands is written, rotated once it reaches 1 MiB. Defaults to
+    /// whkd\output.log in the local app data directory, and - writes it to the console instead.
+    #[clap(long)]
+    output_log: Option<PathBuf>,
+    /// Log more detail: once for every binding registered and every chord which is ignored or not
+    /// bound, twice for everything. Chords which run a command are logged by default
+    #[clap(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Log less: once for warnings and errors only, twice for errors only, three times for nothing
+    #[clap(short, long, global = true, action = ArgAction::Count)]
+    quiet: u8,
+    /// Append the log to this file instead of writing it to stderr
+    #[clap(long, global = true)]
+    log_file: Option<PathBuf>,
+    #[clap(subcommand)]
+    subcommand: Option<SubCommand>,
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Explain how a chord would be handled when pressed in a given application
+    Explain(Explain),
+    /// Print the path of each layer of whkdrc in use
+    ConfigPath,
+    /// List every binding along with the layer of whkdrc it came from
+    List,
 }
 
-fn spawn_shell(shell: Shell) -> Result<()> {
-    let shell_binary = shell.to_string();
+#[derive(Args)]
+struct Explain {
+    /// The chord to look up, e.g. "alt+n"
+    chord: String,
+    /// The application name of the active window (as shown by `Get-Process`)
+    #[clap(long)]
+    app: Option<String>,
+}
 
-    match shell {
-        Shell::Powershell | Shell::Pwsh => {
-            let mut process = Command::new(&shell_binary)
-                .stdin(Stdio::piped())
-                .args(["-Command", "-"])
-                .spawn()?;
+fn explain(whkdrc: &Whkdrc, args: &Explain) -> Result<()> {
+    let chord = Chord::from_str(&args.chord)?;
+    let context = args
+        .app
+        .as_deref()
+        .map_or_else(WindowContext::default, WindowContext::for_app);
+    let app_label = args
+        .app
+        .as_ref()
+        .map_or_else(|| String::from("any application"), |app| format!("'{app}'"));
+
+    match whkdrc.resolve(&chord, &context) {
+        Resolution::Run(command) => println!("{chord} in {app_label} runs: {command}"),
+        Resolution::Ignored => println!("{chord} in {app_label} is ignored"),
+        Resolution::Unbound if whkdrc.is_app_specific(&chord) => {
+            println!("{chord} in {app_label} is swallowed");
+        }
+        Resolution::Unbound => println!("{chord} in {app_label} is not bound"),
+    }
 
-            let mut stdin = process
-                .stdin
-                .take()
-                .ok_or_else(|| eyre!("could not take stdin from powershell session"))?;
+    let reason = match whkdrc.resolve_binding(&chord, &context) {
+        Some(binding) => match binding.process_name.as_deref() {
+            Some("Default") => String::from(
+                "there is no app block entry for this application, so the Default entry is used",
+            ),
+            Some(entry) if binding.command == "Ignore" => {
+                format!("the app block entry for '{entry}' is Ignore")
+            }
+            Some(entry) => format!("the app block entry for '{entry}' matched"),
+            None if whkdrc.is_app_specific(&chord) => String::from(
+                "the app block has no entry for this application and no Default entry, so the global binding is used",
+            ),
+            None => String::from("the chord has a global binding which applies to every application"),
+        },
+        None if whkdrc.is_app_specific(&chord) => String::from(
+            "the chord is registered for an app block with no entry for this application and no Default entry, so the key press is consumed without running anything",
+        ),
+        None => String::from("the chord is not registered by whkd, so the key press is passed through"),
+    };
 
-            writeln!(stdin, "$wshell = New-Object -ComObject wscript.shell")?;
+    println!("reason: {reason}");
 
-            let mut session_stdin = SESSION_STDIN.lock();
-            *session_stdin = Option::from(stdin);
+    Ok(())
+}
+
+fn list(whkdrc: &Whkdrc, provenance: &Provenance) {
+    for binding in whkdrc
+        .app_bindings
+        .iter()
+        .flat_map(|(_, bindings)| bindings)
+        .chain(&whkdrc.bindings)
+    {
+        let chord = Chord::new(&binding.keys);
+        let source = provenance.source(binding).unwrap_or("unknown");
+
+        match &binding.process_name {
+            Some(process_name) => {
+                println!("{chord} [{process_name}]: {} ({source})", binding.command)
+            }
+            None => println!("{chord}: {} ({source})", binding.command),
         }
-        Shell::Cmd => {
-            let mut process = Command::new(&shell_binary)
-                .stdin(Stdio::piped())
-                .args(["-"])
-                .spawn()?;
+    }
+}
+
+/// Reports the new pause state and runs the pause hook
+pub fn pause_toggled(executor: &dyn Executor, whkdrc: &Whkdrc, paused: bool) {
+    let current_state = if paused { "paused" } else { "running" };
 
-            let mut stdin = process
-                .stdin
-                .take()
-                .ok_or_else(|| eyre!("could not take stdin from cmd session"))?;
+    tracing::info!("whkd is now {current_state}");
+    events::publish(&Event::PauseToggled { paused });
 
-            writeln!(stdin, "prompt $S")?;
+    if let Some(command) = &whkdrc.pause_hook {
+        run_hook(executor, &whkdrc.job(command));
+    }
+}
+
+/// Runs the exit hook and shuts `executor` down
+pub fn exit(executor: &dyn Executor, whkdrc: &Whkdrc) {
+    if let Some(command) = &whkdrc.exit_hook {
+        run_hook(executor, &whkdrc.job(command));
+    }
+
+    executor.shutdown();
+}
 
-            let mut session_stdin = SESSION_STDIN.lock();
-            *session_stdin = Option::from(stdin);
+fn register_pause(
+    table: &mut HotkeyTable,
+    whkdrc: &Arc<Whkdrc>,
+    executor: &Arc<dyn Executor>,
+    keys: &[String],
+    pause_handle: PauseHandle,
+) -> Result<(), WHKError> {
+    let (mod_keys, vkey) = parse_keys(keys)?;
+
+    let whkdrc = whkdrc.clone();
+    let executor = executor.clone();
+    table.register_pause_hotkey(vkey, mod_keys.as_slice(), move || {
+        let paused = pause_handle.is_paused();
+        let (executor, whkdrc) = (executor.clone(), whkdrc.clone());
+
+        worker::queue(move || pause_toggled(&*executor, &whkdrc, paused));
+    })?;
+
+    Ok(())
+}
+
+/// A hotkey which could not be registered
+#[derive(Debug)]
+pub struct RegistrationFailure {
+    pub chord: Chord,
+    pub pause: bool,
+    pub error: WHKError,
+}
+
+impl Display for RegistrationFailure {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.pause {
+            write!(
+                f,
+                "Unable to register pause hotkey '{}' (error: {})",
+                self.chord, self.error
+            )
+        } else {
+            write!(f, "Unable to bind '{}' (error: {})", self.chord, self.error)
+        }
+    }
+}
+
+impl RegistrationFailure {
+    const fn new(chord: Chord, pause: bool, error: WHKError) -> Self {
+        Self {
+            chord,
+            pause,
+            error,
+        }
+    }
+
+    /// Tells subscribers that the hotkey could not be registered
+    pub fn publish(&self) {
+        events::publish(&Event::RegistrationFailed {
+            chord: self.chord.to_string(),
+            message: self.error.to_string(),
+        });
+    }
+}
+
+/// Builds the hotkeys for `whkdrc`, returning them along with the hotkeys that could not be
+/// registered. Invalid key names are an error.
+pub fn build_table(
+    whkdrc: &Arc<Whkdrc>,
+    executor: &Arc<dyn Executor>,
+    pause_handle: &PauseHandle,
+) -> Result<(HotkeyTable, Vec<RegistrationFailure>)> {
+    let mut table = HotkeyTable::new();
+    let mut failures = vec![];
+
+    if let Some(keys) = &whkdrc.pause_binding {
+        if let Err(error) = register_pause(&mut table, whkdrc, executor, keys, pause_handle.clone())
+        {
+            failures.push(RegistrationFailure::new(Chord::new(keys), true, error));
+        }
+    }
+
+    // the pause binding takes its chord, so a command bound to the same chord is left out rather
+    // than failing to register, as reported by Diagnostic::PauseBindingConflict
+    let mut registered = whkdrc
+        .pause_binding
+        .iter()
+        .map(|keys| Chord::new(keys))
+        .collect::<Vec<_>>();
+
+    for binding in whkdrc
+        .app_bindings
+        .iter()
+        .flat_map(|(_, bindings)| bindings)
+        .chain(&whkdrc.bindings)
+    {
+        let data = HkmData::try_from(binding)?;
+        tracing::debug!(
+            chord = %data.chord,
+            process = data.process_name.as_deref().unwrap_or_default(),
+            command = data.command,
+            "binding"
+        );
+
+        if !registered.contains(&data.chord) {
+            if let Err(error) = data.register(&mut table, whkdrc, executor) {
+                failures.push(RegistrationFailure::new(data.chord.clone(), false, error));
+            }
+
+            registered.push(data.chord);
+        }
+    }
+
+    Ok((table, failures))
+}
+
+/// Sends the log to stderr, or to `--log-file`, at the level chosen with `-v` and `-q`
+fn init_logging(cli: &Cli) -> Result<()> {
+    let level = match i16::from(cli.verbose) - i16::from(cli.quiet) {
+        ..=-3 => LevelFilter::OFF,
+        -2 => LevelFilter::ERROR,
+        -1 => LevelFilter::WARN,
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_target(false);
+
+    match &cli.log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+
+            subscriber
+                .with_ansi(false)
+                .with_writer(std::sync::Mutex::new(file))
+                .init();
         }
+        None => subscriber.with_writer(std::io::stderr).init(),
     }
 
     Ok(())
 }
 
-#[allow(clippy::too_many_lines)]
 fn main() -> Result<()> {
     color_eyre::install()?;
     let cli = Cli::parse();
+    init_logging(&cli)?;
 
-    let whkdrc = cli.config.map_or_else(
-        || WHKDRC.clone(),
-        |config| {
-            whkd_parser::load(&config)
-                .unwrap_or_else(|_| panic!("could not load whkdrc from {config:?}"))
-        },
-    );
+    let layers = if cli.config.is_empty() {
+        Layers::discovered(config::discover()?)
+    } else {
+        Layers::given(&cli.config)?
+    };
 
-    spawn_shell(whkdrc.shell)?;
+    if matches!(cli.subcommand, Some(SubCommand::ConfigPath)) {
+        for source in layers.sources() {
+            println!("{source}");
+        }
 
-    let mut hkm = HotkeyManager::new();
-    let pause_handle = hkm.pause_handle();
+        return Ok(());
+    }
 
-    if let Some(keys) = whkdrc.pause_binding {
-        let mut mod_keys = vec![];
+    let (whkdrc, provenance) = layers.load()?;
 
-        let (mod_keys, vkey) = if keys.len() == 1 {
-            (vec![], VKey::from_keyname(&keys[0])?)
-        } else {
-            let (trigger, mods) = keys.split_last().unwrap();
-            let vkey = VKey::from_keyname(trigger)?;
-            for m in mods {
-                mod_keys.push(VKey::from_keyname(m)?);
-            }
+    match &cli.subcommand {
+        Some(SubCommand::Explain(args)) => return explain(&whkdrc, args),
+        Some(SubCommand::List) => {
+            list(&whkdrc, &provenance);
+            return Ok(());
+        }
+        Some(SubCommand::ConfigPath) | None => {}
+    }
+
+    tracing::info!(
+        config = %layers.primary().display(),
+        layers = layers.sources().len(),
+        shell = %whkdrc.shell,
+        bindings = whkdrc.chords().len(),
+        "loaded whkdrc"
+    );
+    log_diagnostics(&whkdrc);
+
+    let _instance = instance::lock(&layers.primary(), cli.replace)?;
 
-            (mod_keys, vkey)
-        };
-
-        if let Err(error) = hkm.register_pause_hotkey(vkey, mod_keys.as_slice(), move || {
-            let current_state = if pause_handle.is_paused() {
-                "paused"
-            } else {
-                "running"
-            };
-
-            println!("whkd is now {current_state}");
-
-            if let Some(cmd) = &whkdrc.pause_hook {
-                let mut retry_with_new_session = false;
-                if let Some(session_stdin) = SESSION_STDIN.lock().as_mut() {
-                    if matches!(whkdrc.shell, Shell::Pwsh | Shell::Powershell) {
-                        println!("{cmd}");
-                    }
-
-                    if writeln!(session_stdin, "{cmd}").is_err() {
-                        retry_with_new_session = true;
-                    }
-                }
-
-                if retry_with_new_session && spawn_shell(whkdrc.shell).is_ok() {
-                    if let Some(session_stdin) = SESSION_STDIN.lock().as_mut() {
-                        if matches!(whkdrc.shell, Shell::Pwsh | Shell::Powershell) {
-                            println!("{cmd}");
-                        }
-
-                        if writeln!(session_stdin, "{cmd}").is_err() {
-                            eprintln!("Unable to write to stdin session");
-                        }
-                    }
-                }
+    let output_log = cli.output_log.clone().unwrap_or_else(output::default_path);
+    if output_log.as_os_str() != "-" {
+        match output::open(&output_log) {
+            Ok(()) => {
+                tracing::info!(path = %output_log.display(), "writing the output of commands to the output log")
             }
-        }) {
-            eprintln!(
-                "Unable to register pause hotkey '{mod_keys:?} + {vkey}' (error: {error}), ignoring this binding and continuing...",
-            );
+            Err(error) => tracing::warn!(
+                path = %output_log.display(),
+                %error,
+                "unable to open the output log, writing the output of commands to the console"
+            ),
         }
     }
 
-    let mut mapped = HashMap::new();
-    for (keys, app_bindings) in &whkdrc.app_bindings {
-        for binding in app_bindings {
-            let data = HkmData::try_from(binding)?;
-            mapped
-                .entry(keys.join("+"))
-                .or_insert_with(Vec::new)
-                .push(data);
-        }
+    let executor = cli.executor.executor(ShellProgram::of(&whkdrc))?;
+
+    let whkdrc = Arc::new(whkdrc);
+    let manager = HotkeyManager::new(HotkeyTable::new());
+
+    // unlike on reload, where the previous hotkeys are kept, there is nothing to fall back to
+    let (table, failures) = build_table(&whkdrc, &executor, &manager.pause_handle())?;
+    for failure in failures {
+        tracing::warn!("{failure}, ignoring this binding and continuing...");
     }
 
-    for (_, v) in mapped {
-        let vkey = v[0].vkey;
-        let mod_keys = v[0].mod_keys.as_slice();
-
-        let v = v.clone();
-        hkm.register_hotkey(vkey, mod_keys, move || {
-            let mut retry_with_new_session = false;
-
-            if let Some(session_stdin) = SESSION_STDIN.lock().as_mut() {
-                let app_name = active_win_pos_rs::get_active_window()
-                    .unwrap_or_default()
-                    .app_name;
-
-                let mut matched_cmd = None;
-                let mut default_cmd = None;
-
-                for e in &v {
-                    let cmd = &e.command;
-
-                    if let Some(proc) = &e.process_name {
-                        if *proc == "Default" {
-                            default_cmd = Some(cmd.clone());
-                        }
-
-                        if app_name == *proc {
-                            matched_cmd = Some(cmd.clone());
-                        }
-                    }
-                }
-
-                match (matched_cmd, default_cmd) {
-                    (None, Some(cmd)) | (Some(cmd), _) if cmd != "Ignore" => {
-                        if matches!(whkdrc.shell, Shell::Pwsh | Shell::Powershell) {
-                            println!("{cmd}");
-                        }
-
-                        if writeln!(session_stdin, "{cmd}").is_err() {
-                            retry_with_new_session = true;
-                        }
-                    }
-                    (_, _) => {}
-                }
-            }
+    manager.swap(table);
 
-            if retry_with_new_session && spawn_shell(whkdrc.shell).is_ok() {
-                if let Some(session_stdin) = SESSION_STDIN.lock().as_mut() {
-                    let app_name = active_win_pos_rs::get_active_window()
-                        .unwrap_or_default()
-                        .app_name;
-
-                    let mut matched_cmd = None;
-                    let mut default_cmd = None;
-
-                    for e in &v {
-                        let cmd = &e.command;
-
-                        if let Some(proc) = &e.process_name {
-                            if *proc == "Default" {
-                                default_cmd = Some(cmd.clone());
-                            }
-
-                            if app_name == *proc {
-                                matched_cmd = Some(cmd.clone());
-                            }
-                        }
-                    }
-
-                    match (matched_cmd, default_cmd) {
-                        (None, Some(cmd)) | (Some(cmd), _) if cmd != "Ignore" => {
-                            if matches!(whkdrc.shell, Shell::Pwsh | Shell::Powershell) {
-                                println!("{cmd}");
-                            }
-
-                            if writeln!(session_stdin, "{cmd}").is_err() {
-                                eprintln!("Unable to write to stdin session");
-                            }
-                        }
-                        (_, _) => {}
-                    }
-                }
-            }
-        })?;
+    // whkdc reaches the whkd for a given whkdrc with --config, and the whkd which found its
+    // whkdrc by itself without it
+    let mut sockets = vec![whkd_ipc::socket_name(&layers.primary())];
+    if cli.config.is_empty() {
+        sockets.push(whkd_ipc::SOCKET_NAME.to_string());
+    }
+
+    let daemon = Arc::new(Daemon::new(
+        manager.clone(),
+        layers,
+        whkdrc,
+        cli.executor,
+        executor,
+    ));
+    daemon.watch();
+    daemon.handle_failures();
+
+    for socket in sockets {
+        match whkd_ipc::listen(&socket) {
+            Ok(listener) => ipc::serve(daemon.clone(), listener),
+            Err(error) => tracing::warn!(
+                socket,
+                %error,
+                "unable to listen for IPC connections, whkdc will not be able to control this instance"
+            ),
+        }
     }
 
-    for binding in &whkdrc.bindings {
-        HkmData::try_from(binding)?.register(&mut hkm, whkdrc.shell)?;
+    let handler = daemon.clone();
+    if let Err(error) = ctrlc::set_handler(move || handler.stop()) {
+        tracing::warn!(%error, "unable to set a handler for Ctrl+C");
     }
 
-    hkm.event_loop();
+    manager.event_loop();
+    daemon.shutdown();
 
     Ok(())
 }
@@ -0,0 +1,236 @@
+use parking_lot::Mutex;
+use std::ffi::OsString;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::FormatTime;
+use tracing_subscriber::fmt::time::SystemTime;
+
+/// How large the output log can grow before it is rotated
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+/// How many rotated logs are kept next to the output log, as `output.log.1` and so on
+const ROTATED_LOGS: usize = 3;
+
+static LOG: Mutex<Option<OutputLog>> = Mutex::new(None);
+
+/// Which pipe of a command a line of output came from
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+struct OutputLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl OutputLog {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+        })
+    }
+
+    fn write(&mut self, entry: &str) -> std::io::Result<()> {
+        let length = entry.len() as u64 + 1;
+        if self.size > 0 && self.size + length > MAX_LOG_SIZE {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{entry}")?;
+        self.size += length;
+
+        Ok(())
+    }
+
+    /// Moves the log to `output.log.1`, moving the older logs along and dropping the oldest one,
+    /// and starts a new log
+    fn rotate(&mut self) -> std::io::Result<()> {
+        for index in (1..ROTATED_LOGS).rev() {
+            let _ = std::fs::rename(rotated(&self.path, index), rotated(&self.path, index + 1));
+        }
+
+        std::fs::rename(&self.path, rotated(&self.path, 1))?;
+
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut path = OsString::from(path);
+    path.push(format!(".{index}"));
+    PathBuf::from(path)
+}
+
+/// Where the output log is written unless `--output-log` is given
+pub fn default_path() -> PathBuf {
+    dirs::data_local_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("whkd")
+        .join("output.log")
+}
+
+/// Sends the output of commands to the log at `path` from now on, rather than to the console
+pub fn open(path: &Path) -> std::io::Result<()> {
+    *LOG.lock() = Some(OutputLog::open(path)?);
+    Ok(())
+}
+
+/// Records that `command` is about to be run with `shell`
+pub fn command(shell: &str, command: &str) {
+    write(&command_entry(&timestamp(), shell, command), || {
+        println!("{command}");
+    });
+}
+
+/// Records a line of output from `shell`, along with the command which printed it if it is known
+pub fn line(shell: &str, stream: Stream, command: Option<&str>, line: &str) {
+    write(
+        &line_entry(&timestamp(), shell, stream, command, line),
+        || match stream {
+            Stream::Stdout => println!("{line}"),
+            Stream::Stderr => eprintln!("{line}"),
+        },
+    );
+}
+
+fn command_entry(timestamp: &str, shell: &str, command: &str) -> String {
+    format!("{timestamp} {shell} > {command}")
+}
+
+fn line_entry(
+    timestamp: &str,
+    shell: &str,
+    stream: Stream,
+    command: Option<&str>,
+    line: &str,
+) -> String {
+    let stream_name = match stream {
+        Stream::Stdout => "out",
+        Stream::Stderr => "err",
+    };
+
+    match command {
+        Some(command) => format!("{timestamp} {shell} {stream_name} [{command}] {line}"),
+        None => format!("{timestamp} {shell} {stream_name} {line}"),
+    }
+}
+
+/// Writes `entry` to the log, or calls `console` if there is no log or it cannot be written to
+fn write(entry: &str, console: impl FnOnce()) {
+    let mut log = LOG.lock();
+    let Some(log) = log.as_mut() else {
+        return console();
+    };
+
+    if let Err(error) = log.write(entry) {
+        tracing::warn!(path = %log.path.display(), %error, "unable to write to the output log");
+        console();
+    }
+}
+
+/// The current time in UTC in the same format as the log, e.g. `2024-03-01T17:05:42.123456Z`
+fn timestamp() -> String {
+    let mut timestamp = String::new();
+    let _ = SystemTime.format_time(&mut Writer::new(&mut timestamp));
+    timestamp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entries() {
+        let timestamp = "2024-03-01T17:05:42.123456Z";
+
+        assert_eq!(
+            command_entry(timestamp, "pwsh", "komorebic focus left"),
+            "2024-03-01T17:05:42.123456Z pwsh > komorebic focus left"
+        );
+        assert_eq!(
+            line_entry(
+                timestamp,
+                "pwsh",
+                Stream::Stderr,
+                Some("komorebic focus left"),
+                "not running"
+            ),
+            "2024-03-01T17:05:42.123456Z pwsh err [komorebic focus left] not running"
+        );
+        assert_eq!(
+            line_entry(timestamp, "cmd", Stream::Stdout, None, "hello"),
+            "2024-03-01T17:05:42.123456Z cmd out hello"
+        );
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = std::env::temp_dir().join(format!("whkd-output-{}", std::process::id()));
+        let path = dir.join("output.log");
+        let mut log = OutputLog::open(&path).unwrap();
+
+        // each entry is 1 KiB along with its newline
+        let entry = "x".repeat(1023);
+        let entries_per_log = usize::try_from(MAX_LOG_SIZE / 1024).unwrap();
+
+        for _ in 0..entries_per_log {
+            log.write(&entry).unwrap();
+        }
+
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), MAX_LOG_SIZE);
+        assert!(!rotated(&path, 1).exists());
+
+        // the next entry would take the log over the limit, so it starts a new one
+        log.write("first").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\n");
+        assert_eq!(
+            std::fs::metadata(rotated(&path, 1)).unwrap().len(),
+            MAX_LOG_SIZE
+        );
+
+        // only the newest rotated logs are kept
+        for index in 0..ROTATED_LOGS {
+            log.rotate().unwrap();
+            log.write(&format!("entry {index}")).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read_to_string(rotated(&path, 1)).unwrap(),
+            format!("entry {}\n", ROTATED_LOGS - 2)
+        );
+        assert_eq!(
+            std::fs::read_to_string(rotated(&path, ROTATED_LOGS)).unwrap(),
+            "first\n"
+        );
+        assert!(!rotated(&path, ROTATED_LOGS + 1).exists());
+
+        // a log which is opened again carries on from its size
+        drop(log);
+        let log = OutputLog::open(&path).unwrap();
+        assert_eq!(log.size, std::fs::metadata(&path).unwrap().len());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            format!("entry {}\n", ROTATED_LOGS - 1)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use whkd_core::LineTerminator;
+use whkd_core::Shell;
+use whkd_core::ShellInit;
+use whkd_core::Whkdrc;
+
+/// A shell along with the extra arguments and commands it is started with, and how commands are
+/// passed to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShellProgram {
+    pub shell: Shell,
+    pub args: Vec<String>,
+    pub init: Option<ShellInit>,
+    /// Set with `.echo`
+    pub echo: Option<bool>,
+}
+
+impl ShellProgram {
+    pub const fn new(shell: Shell) -> Self {
+        Self {
+            shell,
+            args: vec![],
+            init: None,
+            echo: None,
+        }
+    }
+
+    /// The shell set with `.shell` in `whkdrc`, along with its arguments and `.shell_init`
+    pub fn of(whkdrc: &Whkdrc) -> Self {
+        Self {
+            shell: whkdrc.shell.clone(),
+            args: whkdrc.shell_args.clone(),
+            init: whkdrc.shell_init.clone(),
+            echo: whkdrc.echo,
+        }
+    }
+
+    /// The program which is started for the shell
+    pub fn program(&self) -> &str {
+        match &self.shell {
+            Shell::Cmd => "cmd",
+            Shell::Powershell => "powershell",
+            Shell::Pwsh => "pwsh",
+            Shell::Nu => "nu",
+            Shell::Bash => "bash",
+            Shell::Wsl => "wsl",
+            Shell::Custom { program, .. } => program,
+            Shell::None => "",
+        }
+    }
+
+    /// The arguments which make the shell read commands from its standard input
+    pub fn session_args(&self) -> Vec<String> {
+        let args: &[&str] = match self.shell {
+            Shell::Powershell | Shell::Pwsh => &["-Command", "-"],
+            Shell::Cmd => &["-"],
+            Shell::Bash => &["-s"],
+            Shell::Nu | Shell::Wsl | Shell::Custom { .. } | Shell::None => &[],
+        };
+
+        self.with_args(args)
+    }
+
+    /// The arguments which make the shell run `command` and exit. For cmd these are only the
+    /// extra arguments, and the command is passed with [`ShellProgram::raw_command_line`].
+    pub fn command_args(&self, command: &str) -> Vec<String> {
+        let args: &[&str] = match self.shell {
+            Shell::Powershell | Shell::Pwsh => &["-Command", command],
+            Shell::Cmd => &[],
+            Shell::Nu | Shell::Bash => &["-c", command],
+            Shell::Wsl => &["-e", "sh", "-c", command],
+            Shell::Custom { .. } | Shell::None => &[command],
+        };
+
+        self.with_args(args)
+    }
+
+    /// The end of the command line which makes the shell run `command`, for shells that do not
+    /// split their command line in the way that [`std::process::Command`] quotes arguments. It is
+    /// passed as it is after [`ShellProgram::command_args`].
+    ///
+    /// cmd would see `\"` for each double quote in `command` if it was quoted as an argument. With
+    /// `/S`, it only strips the outer quotes and runs the rest exactly as it was written.
+    pub fn raw_command_line(&self, command: &str) -> Option<String> {
+        match self.shell {
+            Shell::Cmd => Some(format!("/S /C \"{command}\"")),
+            _ => None,
+        }
+    }
+
+    fn with_args(&self, args: &[&str]) -> Vec<String> {
+        self.args
+            .iter()
+            .cloned()
+            .chain(args.iter().map(ToString::to_string))
+            .collect()
+    }
+
+    /// The commands written to a new session before any others, which are the ones from
+    /// `.shell_init` after or instead of the defaults for the shell
+    pub fn init(&self) -> Vec<String> {
+        let defaults: &[&str] = match self.shell {
+            Shell::Powershell | Shell::Pwsh => &["$wshell = New-Object -ComObject wscript.shell"],
+            Shell::Cmd => &["prompt $S"],
+            Shell::Nu | Shell::Bash | Shell::Wsl | Shell::Custom { .. } | Shell::None => &[],
+        };
+
+        match &self.init {
+            Some(init) if init.replace => init.commands.clone(),
+            Some(init) => defaults
+                .iter()
+                .map(ToString::to_string)
+                .chain(init.commands.iter().cloned())
+                .collect(),
+            None => defaults.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    /// A command which prints `marker` on a line of its own, for shells where it is known
+    pub fn print(&self, marker: &str) -> Option<String> {
+        match self.shell {
+            Shell::Powershell | Shell::Pwsh => Some(format!("Write-Output '{marker}'")),
+            Shell::Cmd => Some(format!("@echo {marker}")),
+            Shell::Nu => Some(format!("print '{marker}'")),
+            Shell::Bash | Shell::Wsl => Some(format!("echo '{marker}'")),
+            Shell::Custom { .. } | Shell::None => None,
+        }
+    }
+
+    /// What ends each line written to a session
+    pub fn terminator(&self) -> &'static str {
+        match &self.shell {
+            Shell::Custom { terminator, .. } => terminator.as_str(),
+            _ => LineTerminator::default().as_str(),
+        }
+    }
+
+    /// Whether commands are written to the output log before they run, which is done for
+    /// PowerShell unless `.echo` says otherwise
+    pub fn echoes(&self) -> bool {
+        self.echo
+            .unwrap_or(matches!(self.shell, Shell::Powershell | Shell::Pwsh))
+    }
+}
+
+impl Display for ShellProgram {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.shell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmd_command_line() {
+        let cmd = ShellProgram {
+            args: vec![String::from("/Q")],
+            ..ShellProgram::new(Shell::Cmd)
+        };
+        let command = r#"start "" "C:\Program Files\tool.exe" --name "two words""#;
+
+        assert_eq!(cmd.command_args(command), vec!["/Q"]);
+        assert_eq!(
+            cmd.raw_command_line(command).as_deref(),
+            Some(r#"/S /C "start "" "C:\Program Files\tool.exe" --name "two words"""#)
+        );
+
+        let pwsh = ShellProgram::new(Shell::Pwsh);
+        assert_eq!(pwsh.command_args(command), vec!["-Command", command]);
+        assert_eq!(pwsh.raw_command_line(command), None);
+    }
+}
@@ -0,0 +1,27 @@
+use parking_lot::Mutex;
+use std::sync::mpsc;
+use std::sync::mpsc::Sender;
+
+type Task = Box<dyn FnOnce() + Send>;
+
+static QUEUE: Mutex<Option<Sender<Task>>> = Mutex::new(None);
+
+/// Runs `task` on the worker thread once the tasks queued before it have run, so that the hotkey
+/// hook is never held up by starting a shell session or waiting for another whkd
+pub fn queue(task: impl FnOnce() + Send + 'static) {
+    let mut queue = QUEUE.lock();
+    let sender = queue.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel::<Task>();
+        std::thread::spawn(move || {
+            for task in receiver {
+                task();
+            }
+        });
+
+        sender
+    });
+
+    if sender.send(Box::new(task)).is_err() {
+        tracing::error!("the worker thread has stopped, unable to run the hotkey");
+    }
+}
@@ -0,0 +1,196 @@
+#![warn(clippy::all)]
+#![allow(clippy::missing_errors_doc)]
+
+use clap::Args;
+use clap::Parser;
+use clap::Subcommand;
+use color_eyre::eyre::eyre;
+use color_eyre::eyre::Result;
+use std::path::PathBuf;
+use whkd_ipc::Outcome;
+use whkd_ipc::Request;
+use whkd_ipc::Response;
+
+#[derive(Parser)]
+#[clap(author, about, version)]
+struct Cli {
+    /// Talk to the whkd running with this whkdrc, rather than the one which found its whkdrc by
+    /// itself
+    #[clap(short, long, global = true)]
+    config: Option<PathBuf>,
+    #[clap(subcommand)]
+    subcommand: SubCommand,
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Pause all hotkeys except the pause hotkey
+    Pause,
+    /// Resume all hotkeys
+    Resume,
+    /// Toggle between paused and running
+    TogglePause,
+    /// Reload whkdrc
+    Reload,
+    /// Show the state of the running whkd
+    Status,
+    /// Stop whkd
+    Stop,
+    /// Run the binding for a chord as if it was pressed
+    Trigger(Trigger),
+    /// Bind a chord which is not already bound, without editing whkdrc
+    Register(Register),
+    /// Change the binding of a chord which is already bound, without editing whkdrc
+    Replace(Register),
+    /// Remove the binding of a chord, without editing whkdrc
+    Unregister(Unregister),
+    /// List the bound chords, marking the ones which were registered at runtime
+    Bindings,
+    /// Print an event as a line of JSON for everything that happens in whkd until it exits
+    Subscribe,
+}
+
+#[derive(Args)]
+struct Trigger {
+    /// The chord to trigger, e.g. "alt+h"
+    chord: String,
+    /// Resolve app-specific bindings for this application instead of the active window
+    #[clap(long)]
+    app: Option<String>,
+}
+
+#[derive(Args)]
+struct Register {
+    /// The chord to bind, e.g. "alt+h"
+    chord: String,
+    /// The command to run when the chord is pressed
+    command: String,
+    /// Remove the binding again after this many seconds
+    #[clap(long)]
+    ttl: Option<u64>,
+}
+
+#[derive(Args)]
+struct Unregister {
+    /// The chord to unbind, e.g. "alt+h"
+    chord: String,
+}
+
+impl From<&SubCommand> for Request {
+    fn from(value: &SubCommand) -> Self {
+        match value {
+            SubCommand::Pause => Self::Pause,
+            SubCommand::Resume => Self::Resume,
+            SubCommand::TogglePause => Self::TogglePause,
+            SubCommand::Reload => Self::Reload,
+            SubCommand::Status => Self::Status,
+            SubCommand::Stop => Self::Stop,
+            SubCommand::Trigger(args) => Self::Trigger {
+                chord: args.chord.clone(),
+                app: args.app.clone(),
+            },
+            SubCommand::Register(args) => Self::Register {
+                chord: args.chord.clone(),
+                command: args.command.clone(),
+                ttl: args.ttl,
+            },
+            SubCommand::Replace(args) => Self::Replace {
+                chord: args.chord.clone(),
+                command: args.command.clone(),
+                ttl: args.ttl,
+            },
+            SubCommand::Unregister(args) => Self::Unregister {
+                chord: args.chord.clone(),
+            },
+            SubCommand::Bindings => Self::ListBindings,
+            SubCommand::Subscribe => Self::Subscribe,
+        }
+    }
+}
+
+fn subscribe(socket: &str) -> Result<()> {
+    let subscription = whkd_ipc::subscribe(socket).map_err(|error| {
+        eyre!("could not communicate with whkd (error: {error}), is it running?")
+    })?;
+
+    for event in subscription {
+        println!("{}", serde_json::to_string(&event?)?);
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+    let cli = Cli::parse();
+
+    let socket = cli
+        .config
+        .as_deref()
+        .map_or_else(|| whkd_ipc::SOCKET_NAME.to_string(), whkd_ipc::socket_name);
+
+    if matches!(cli.subcommand, SubCommand::Subscribe) {
+        return subscribe(&socket);
+    }
+
+    let response = whkd_ipc::send(&socket, &Request::from(&cli.subcommand)).map_err(|error| {
+        eyre!("could not communicate with whkd (error: {error}), is it running?")
+    })?;
+
+    match response {
+        Response::Ok => {}
+        Response::Status(status) => {
+            println!("pid: {}", status.pid);
+            println!(
+                "state: {}",
+                if status.paused { "paused" } else { "running" }
+            );
+            println!("config: {}", status.config.display());
+            println!("shell: {}", status.shell);
+            println!("bindings: {}", status.bindings);
+            if let Some(session) = status.session {
+                println!("session: {session}");
+            }
+        }
+        Response::Bindings { bindings } => {
+            for binding in bindings {
+                let command = if binding.app_specific {
+                    binding.command.map_or_else(
+                        || String::from("(app-specific)"),
+                        |command| format!("{command} (app-specific)"),
+                    )
+                } else {
+                    binding.command.unwrap_or_default()
+                };
+
+                let source = match (binding.runtime, binding.expires_in) {
+                    (true, Some(expires_in)) => format!(" [runtime, expires in {expires_in}s]"),
+                    (true, None) => String::from(" [runtime]"),
+                    (false, _) => String::new(),
+                };
+
+                println!("{}: {command}{source}", binding.chord);
+            }
+        }
+        Response::Triggered { command, outcome } => {
+            if let Some(command) = command {
+                println!("command: {command}");
+            }
+
+            match outcome {
+                Outcome::Sent => println!("outcome: sent to the shell session"),
+                Outcome::Failed { message } => return Err(eyre!(message)),
+                Outcome::Ignored => println!("outcome: ignored for this application"),
+                Outcome::Unbound => println!("outcome: nothing is bound to this chord"),
+                Outcome::Paused => println!("outcome: whkd is paused"),
+                Outcome::PauseToggled { paused } => println!(
+                    "outcome: whkd is now {}",
+                    if paused { "paused" } else { "running" }
+                ),
+            }
+        }
+        Response::Error { message } => return Err(eyre!(message)),
+    }
+
+    Ok(())
+}